@@ -1,8 +1,8 @@
 print("Before:", config)
 config.paths = {
-	projects = "D:/Repo",
-	download = "D:/Downloads",
-	build = "D:/Repo/test-project/build",
+	projects = "~/Repo",
+	download = "${XDG_DOWNLOAD_DIR}",
+	build = "../build",
 }
 config.features.show_docker_logs = true
 v.print("After:", config)
@@ -0,0 +1,63 @@
+---@meta
+-- This file is generated by `slua types generate`. Do not edit it by hand.
+
+--- A write to a config value
+---@class ConfigOrigin
+---@field key string Key that was written, a whole section when it was replaced
+---@field layer "default"|"system"|"saved"|"user"|"project"|"profile"|"env"|"cli" Config layer that was loading
+---@field location { chunk: string, line: integer }? Lua code that made the write
+
+--- Paths to search for files.
+---
+--- `~`, `$VAR` and `${VAR}` are expanded and relative paths are relative to the file that sets them.
+---@class Paths
+---@field projects string Path to the projects directory where all your cloned repositories live
+---@field download string Path where external dependencies should be downloaded/installed
+---@field build string Path where the build will occur
+---@field replace fun(self: Paths, values: Paths) Replace every value, fields missing from `values` are reset to their default
+---@field resolved fun(self: Paths): Paths Plain table of the final values, with defaults and expansions applied
+---@field on_change fun(self: Paths, key: string, f: fun(old: any, new: any)) Call `f` whenever the value of `key` (relative to this section) changes
+---@field origin fun(self: Paths, key: string): ConfigOrigin[] Every write to `key` (relative to this section), oldest first
+
+--- Application optional features, flags are declared by rust and plugins
+---@class Features
+---@field show_docker_logs boolean show or hide docker command stdout responses
+---@field [string] boolean|number|string Flags declared by plugins
+---@field replace fun(self: Features, values: Features) Replace every value, fields missing from `values` are reset to their default
+---@field resolved fun(self: Features): Features Plain table of the final values, with defaults and expansions applied
+---@field on_change fun(self: Features, key: string, f: fun(old: any, new: any)) Call `f` whenever the value of `key` (relative to this section) changes
+---@field origin fun(self: Features, key: string): ConfigOrigin[] Every write to `key` (relative to this section), oldest first
+---@field declare fun(self: Features, flag: { name: string, default: boolean|number|string, description: string? }) Declare a flag, its type is the type of `default`
+---@field flags fun(self: Features): { name: string, default: boolean|number|string, description: string }[] Every declared flag, sorted by name
+
+--- A value that is printed as `<redacted>`, created with `secret(...)`
+---@class Secret
+---@field source string Where the value was read from, e.g. `env:GITHUB_TOKEN`
+---@field reveal fun(self: Secret): string The secret value, never print it
+
+--- Secrets used by plugins, printed as `<redacted>`
+---@class Secrets
+---@field [string] Secret Secrets created with `secret(...)`
+---@field replace fun(self: Secrets, values: Secrets) Replace every value, fields missing from `values` are reset to their default
+---@field resolved fun(self: Secrets): Secrets Plain table of the final values, with defaults and expansions applied
+---@field on_change fun(self: Secrets, key: string, f: fun(old: any, new: any)) Call `f` whenever the value of `key` (relative to this section) changes
+---@field origin fun(self: Secrets, key: string): ConfigOrigin[] Every write to `key` (relative to this section), oldest first
+
+--- Application Configuration
+---@class Config
+---@field paths Paths
+---@field features Features
+---@field secrets Secrets
+---@field replace fun(self: Config, values: Config) Replace every value, fields missing from `values` are reset to their default
+---@field resolved fun(self: Config): Config Plain table of the final values, with defaults and expansions applied
+---@field on_change fun(self: Config, key: string, f: fun(old: any, new: any)) Call `f` whenever the value of `key` (relative to this section) changes
+---@field origin fun(self: Config, key: string): ConfigOrigin[] Every write to `key` (relative to this section), oldest first
+---@field save fun(self: Config, path: string?): string Write the config to `path` (json or toml), by default the saved config that is loaded before init.lua. Returns the path
+---@field profile fun(name: string, f: fun(c: Config)) Define a profile, `f` is called with the config when it is selected with `--profile` or `SLUA_PROFILE`
+---@field active_profile string? Name of the selected profile, read only
+
+---@type Config
+config = nil
+
+---@type fun(source: string|{ env: string?, file: string?, store: string?, path: string? }): Secret
+secret = nil
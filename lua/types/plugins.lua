@@ -1,27 +1,26 @@
---- @meta
+---@meta
+-- This file is generated by `slua types generate`. Do not edit it by hand.
 
 --- The plugin information stripped of the event hooks
---- @class PluginInfo
---- @field name string
---- @field version string
---- @field author string
---- @field description string
+---@class PluginInfo
+---@field name string
+---@field version string
+---@field author string
+---@field description string
 
 --- A table of plugin information and event hooks
---- @class Plugin
---- @field name string
---- @field version string
---- @field author string
---- @field description string
---- @field setup? fun(plugin: PluginInfo)
-Plugin = {}
+---@class Plugin
+---@field name string
+---@field version string
+---@field author string
+---@field description string
+---@field setup? fun(plugin: PluginInfo) Called when the plugin is added
 
 --- Module for adding plugins
---- @class plugins
---- @field plugins Plugin[]
+---@class plugins
+---@field plugins Plugin[] All plugins that have been added
 plugins = {}
 
 --- Add a new plugin
---- @param plugin Plugin
+---@param plugin Plugin
 function plugins.new_plugin(plugin) end
-
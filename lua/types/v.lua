@@ -0,0 +1,10 @@
+---@meta
+-- This file is generated by `slua types generate`. Do not edit it by hand.
+
+--- Root namespace
+---@class v
+v = {}
+
+--- Pretty print all arguments to stdout
+---@param ... any
+function v.print(...) end
@@ -0,0 +1,280 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, Fields, Ident, LitStr, Type};
+
+/// Derive everything needed to expose a config struct to lua.
+///
+/// Generates `LuaConfig`, `FromLua`, `UserData` (field getters/setters and the methods from
+/// `config::add_methods`), `LuaFmt`, `LuaClass` and `Default` implementations. Every field type
+/// must implement `ConfigValue`, except for skipped fields.
+///
+/// `Default` uses the same defaults as a table with missing fields, so do not derive it as well.
+///
+/// Doc comments on the struct and its fields are used for the generated type definitions.
+///
+/// # Attributes
+///
+/// Struct:
+/// - `#[lua(name = "Paths")]`: lua class name, defaults to the struct name
+/// - `#[lua(key = "paths")]`: key of the section inside of `config`, used in error messages
+/// - `#[lua(methods = Self::add_methods)]`: function that adds more methods to the userdata
+/// - `#[lua(fields = Self::add_fields)]`: function that adds more (read only) fields to the userdata
+///
+/// Field:
+/// - `#[lua(rename = "name")]`: name of the field in lua
+/// - `#[lua(default = expr)]`: value of the field in `Default` and when it is missing from a table,
+///   converted with `From`. Defaults to `Default::default()`
+/// - `#[lua(skip)]`: do not expose the field to lua. It has its default when created from a table
+///   and keeps its value when the section is replaced
+#[proc_macro_derive(LuaConfig, attributes(lua))]
+pub fn derive_lua_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+struct Field {
+    ident: Ident,
+    ty: Type,
+    name: String,
+    doc: String,
+    default: Option<Expr>,
+    skip: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&input.generics, "LuaConfig does not support generic structs"));
+    }
+
+    let mut name = ident.to_string();
+    let mut key = String::new();
+    let mut methods = None;
+    let mut extra_fields = None;
+    for attr in lua_attrs(&input.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("key") {
+                key = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("methods") {
+                methods = Some(meta.value()?.parse::<Expr>()?);
+            } else if meta.path.is_ident("fields") {
+                extra_fields = Some(meta.value()?.parse::<Expr>()?);
+            } else {
+                return Err(meta.error("expected `name`, `key`, `methods` or `fields`"));
+            }
+            Ok(())
+        })?;
+    }
+    let doc = doc_comment(&input.attrs);
+    let methods = methods.map(|methods| quote! { #methods(methods); });
+    let extra_fields = extra_fields.map(|extra_fields| quote! { #extra_fields(fields); });
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().map(parse_field).collect::<syn::Result<Vec<_>>>()?,
+            _ => return Err(syn::Error::new_spanned(ident, "LuaConfig requires a struct with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(ident, "LuaConfig can only be derived for structs")),
+    };
+    let exposed = fields.iter().filter(|f| !f.skip).collect::<Vec<_>>();
+
+    let names = exposed.iter().map(|f| &f.name).collect::<Vec<_>>();
+    let idents = exposed.iter().map(|f| &f.ident).collect::<Vec<_>>();
+    let types = exposed.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let docs = exposed.iter().map(|f| &f.doc).collect::<Vec<_>>();
+    // `Default` and tables with missing fields use the same value
+    let default_value = |f: &Field| match &f.default {
+        Some(default) => quote! { ::std::convert::From::from(#default) },
+        None => quote! { ::std::default::Default::default() },
+    };
+    let defaults = fields.iter().map(|f| {
+        let field = &f.ident;
+        let default = default_value(f);
+        quote! { #field: #default }
+    });
+
+    let from_table = fields.iter().map(|f| {
+        let field = &f.ident;
+        let name = &f.name;
+        let default = default_value(f);
+        if f.skip {
+            return quote! { #field: #default };
+        }
+        quote! { #field: __config::field_or(lua, table, Self::KEY, #name, || #default)? }
+    });
+
+    Ok(quote! {
+        const _: () = {
+            use ::slua::modules::config as __config;
+            use ::slua::modules::config::ConfigValue as _;
+
+            impl __config::LuaConfig for #ident {
+                const NAME: &'static str = #name;
+                const KEY: &'static str = #key;
+                const FIELDS: &'static [&'static str] = &[#(#names),*];
+
+                fn keys(lua: &::mlua::Lua) -> ::std::vec::Vec<::std::string::String> {
+                    let mut keys = ::std::vec::Vec::new();
+                    #(
+                        keys.push(::std::string::String::from(#names));
+                        for key in <#types as __config::ConfigValue>::keys(lua) {
+                            keys.push(::std::format!("{}.{}", #names, key));
+                        }
+                    )*
+                    keys
+                }
+
+                fn json_schema(lua: &::mlua::Lua) -> __config::JsonValue {
+                    __config::schema_object(#doc, ::std::vec![
+                        #((#names, <#types as __config::ConfigValue>::json_schema(lua), #docs),)*
+                    ])
+                }
+
+                fn get_field<'lua>(
+                    &self,
+                    lua: &'lua ::mlua::Lua,
+                    key: &str,
+                ) -> ::mlua::Result<::std::option::Option<::mlua::Value<'lua>>> {
+                    match key {
+                        #(#names => self.#idents.to_lua(lua).map(::std::option::Option::Some),)*
+                        _ => ::std::result::Result::Ok(::std::option::Option::None),
+                    }
+                }
+
+                fn set_field<'lua>(
+                    &mut self,
+                    lua: &'lua ::mlua::Lua,
+                    key: &str,
+                    value: ::mlua::Value<'lua>,
+                ) -> ::mlua::Result<bool> {
+                    match key {
+                        #(#names => __config::assign(lua, &mut self.#idents, Self::KEY, #names, value).map(|_| true),)*
+                        _ => ::std::result::Result::Ok(false),
+                    }
+                }
+
+                fn resolved<'lua>(&self, lua: &'lua ::mlua::Lua) -> ::mlua::Result<::mlua::Table<'lua>> {
+                    let table = lua.create_table()?;
+                    #(table.set(#names, self.#idents.resolved_lua(lua)?)?;)*
+                    ::std::result::Result::Ok(table)
+                }
+
+                fn from_table<'lua>(table: &::mlua::Table<'lua>, lua: &'lua ::mlua::Lua) -> ::mlua::Result<Self> {
+                    ::std::result::Result::Ok(Self {
+                        #(#from_table,)*
+                    })
+                }
+
+                fn replace_with(&mut self, new: Self) {
+                    #(__config::ConfigValue::replace_with(&mut self.#idents, new.#idents);)*
+                }
+            }
+
+            impl ::std::default::Default for #ident {
+                fn default() -> Self {
+                    Self {
+                        #(#defaults,)*
+                    }
+                }
+            }
+
+            impl<'lua> ::mlua::FromLua<'lua> for #ident {
+                fn from_lua(value: ::mlua::Value<'lua>, lua: &'lua ::mlua::Lua) -> ::mlua::Result<Self> {
+                    __config::from_lua(value, lua)
+                }
+            }
+
+            impl<'lua> ::slua::lua::LuaFmt<'lua> for #ident {
+                fn lua_fmt(&self, pretty: bool, indent: usize) -> ::std::string::String {
+                    ::slua::lua::LuaStructFormat::new(pretty, indent)
+                        #(.field(#names, &self.#idents))*
+                        .to_string()
+                }
+            }
+
+            impl ::slua::types::LuaClass for #ident {
+                fn lua_class() -> ::slua::types::Class {
+                    __config::method_definitions::<Self>(
+                        ::slua::types::Class::new(#name, #doc)
+                            #(.field(#names, <#types as __config::ConfigValue>::lua_type(), #docs))*
+                    )
+                }
+            }
+
+            impl ::mlua::UserData for #ident {
+                fn add_fields<'lua, F: ::mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+                    #(
+                        fields.add_field_method_get(#names, |lua, this: &Self| this.#idents.to_lua(lua));
+                        // Observers run after the userdata is no longer borrowed, so they can read the config
+                        fields.add_field_function_set(#names, |lua, this: ::mlua::AnyUserData<'lua>, value: ::mlua::Value<'lua>| {
+                            __config::with_userdata_mut(&this, |this: &mut Self| {
+                                __config::assign(lua, &mut this.#idents, Self::KEY, #names, value)
+                            })??;
+                            __config::flush(lua)
+                        });
+                    )*
+                    #extra_fields
+                }
+
+                fn add_methods<'lua, M: ::mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                    __config::add_methods(methods);
+                    #methods
+                }
+            }
+        };
+    })
+}
+
+fn parse_field(field: &syn::Field) -> syn::Result<Field> {
+    let ident = field.ident.clone().expect("named field");
+    let mut parsed = Field {
+        name: ident.to_string(),
+        ident,
+        ty: field.ty.clone(),
+        doc: doc_comment(&field.attrs),
+        default: None,
+        skip: false,
+    };
+
+    for attr in lua_attrs(&field.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                parsed.name = meta.value()?.parse::<LitStr>()?.value();
+            } else if meta.path.is_ident("default") {
+                parsed.default = Some(meta.value()?.parse::<Expr>()?);
+            } else if meta.path.is_ident("skip") {
+                parsed.skip = true;
+            } else {
+                return Err(meta.error("expected `rename`, `default` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+fn lua_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("lua"))
+}
+
+/// Join all `///` lines, without the leading space rustdoc adds
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(s), .. }) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').unwrap_or(&line).to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
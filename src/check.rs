@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+use mlua::{Lua, Table, Value};
+
+use crate::lua::{caller_location, Location};
+use crate::modules::{Plugin, Plugins};
+use crate::runtime;
+
+/// A single problem found while checking the config
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{location}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Dry-run state stored in the lua app data.
+///
+/// While enabled, modules report invalid values here instead of raising errors
+/// so that every problem can be collected in one run, and side-effecting
+/// hooks (e.g. plugin `setup`) are not called.
+#[derive(Debug, Default)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// Turn on check mode for the lua state
+    pub fn enable(lua: &Lua) {
+        lua.set_app_data(Diagnostics::default());
+    }
+
+    /// Whether the lua state is in check mode
+    pub fn enabled(lua: &Lua) -> bool {
+        lua.app_data_ref::<Diagnostics>().is_some()
+    }
+
+    /// Record a problem at the location of the calling lua code.
+    ///
+    /// Returns `false` when check mode is not enabled and nothing was recorded.
+    pub fn report<S: Into<String>>(lua: &Lua, message: S) -> bool {
+        let location = caller_location(lua);
+        Self::report_at(lua, location, message)
+    }
+
+    /// Record a problem at an explicit location.
+    ///
+    /// Returns `false` when check mode is not enabled and nothing was recorded.
+    pub fn report_at<S: Into<String>>(lua: &Lua, location: Option<Location>, message: S) -> bool {
+        match lua.app_data_mut::<Diagnostics>() {
+            Some(mut diagnostics) => {
+                diagnostics.0.push(Diagnostic { location, message: message.into() });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Disable check mode and return everything that was reported
+    pub fn take(lua: &Lua) -> Vec<Diagnostic> {
+        lua.remove_app_data::<Diagnostics>().map(|d| d.0).unwrap_or_default()
+    }
+}
+
+/// Load every config layer in check mode and validate the resulting plugins.
+///
+/// Errors raised while loading are reported as a diagnostic instead of
+/// stopping the check, so the result contains every problem that was found.
+pub fn check_init(lua: &Lua, init: &str) -> Vec<Diagnostic> {
+    Diagnostics::enable(lua);
+    let result = runtime::load_config(lua, init);
+    let mut diagnostics = Diagnostics::take(lua);
+
+    if let Err(err) = result {
+        diagnostics.push(Diagnostic { location: None, message: err.to_string() });
+    }
+
+    // Plugins added to `plugins.plugins` directly skip the validation in `new_plugin`
+    match Plugins::module(lua).and_then(|module| module.get::<_, Table>("plugins")) {
+        Ok(plugins) => {
+            for (i, plugin) in plugins.sequence_values::<Value>().enumerate() {
+                let problems = match plugin {
+                    Ok(plugin) => Plugin::problems(&plugin),
+                    Err(err) => vec![err.to_string()],
+                };
+                diagnostics.extend(problems.into_iter().map(|problem| Diagnostic {
+                    location: None,
+                    message: format!("plugins.plugins[{}]: {problem}", i + 1),
+                }));
+            }
+        }
+        Err(err) => diagnostics.push(Diagnostic { location: None, message: err.to_string() }),
+    }
+
+    diagnostics
+}
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use slua::modules::config;
+
+/// Standalone lua runtime with the slua modules preloaded
+#[derive(Debug, Parser)]
+#[command(name = "slua", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Directory containing `init.lua` and the modules it requires
+    ///
+    /// Defaults to the first existing directory of `$SLUA_CONFIG_HOME`,
+    /// `$XDG_CONFIG_HOME/slua` and `~/.config/slua`
+    #[arg(short, long, value_name = "DIR", global = true)]
+    pub config_dir: Option<PathBuf>,
+
+    /// Module that is required after the environment is set up
+    #[arg(short, long, value_name = "MODULE", default_value = "init", global = true)]
+    pub init: String,
+
+    /// Extra `package.path` entries appended after the config directory, e.g. `./lib/?.lua`
+    #[arg(short = 'p', long = "lua-path", value_name = "PATTERN", global = true)]
+    pub lua_paths: Vec<String>,
+
+    /// Log level; overrides `RUST_LOG` when given
+    #[arg(short, long, value_name = "LEVEL", global = true)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Raise an error for unknown config keys and values of the wrong type instead of ignoring them
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    /// Override a config value after every config file is loaded, e.g. `paths.build=/tmp/build`
+    ///
+    /// Applied after the `SLUA_<SECTION>__<KEY>` environment variables. Can be repeated
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true, value_parser = config::parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    /// Config profile applied on top of the config files, defined with `config.profile(name, f)`
+    ///
+    /// Defaults to `$SLUA_PROFILE`
+    #[arg(long, value_name = "NAME", global = true)]
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a lua script with the standard modules preloaded
+    ///
+    /// `init.lua` is not loaded; `require 'init'` from the script loads every config layer,
+    /// including the profile and overrides, when the config is needed.
+    Run {
+        /// Path to the lua script
+        script: PathBuf,
+
+        /// Arguments exposed to the script through the `arg` table and `...`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Start an interactive repl after `init.lua` is loaded
+    Repl {
+        /// File to persist the history to; defaults to `$XDG_STATE_HOME/slua/history`
+        #[arg(long, value_name = "FILE")]
+        history: Option<PathBuf>,
+
+        /// Do not read or write a history file
+        #[arg(long, conflicts_with = "history")]
+        no_history: bool,
+    },
+
+    /// Validate `init.lua`, the config and all plugins without running plugin `setup` hooks
+    ///
+    /// Every problem is reported as `file:line: message` and the exit code is non-zero
+    /// when any problem is found.
+    Check,
+
+    /// Inspect the plugins registered by `init.lua`
+    Plugins {
+        #[command(subcommand)]
+        command: PluginsCommand,
+    },
+
+    /// Inspect the effective config after `init.lua` is loaded
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Re-run `init.lua` whenever a lua file in the config directory changes
+    Watch,
+
+    /// Lua language server type definitions
+    Types {
+        #[command(subcommand)]
+        command: TypesCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TypesCommand {
+    /// Write `---@meta` definition files for the provided modules and types
+    Generate {
+        /// Directory the definition files are written to
+        dir: PathBuf,
+
+        /// `.luarc.json` to create or update; defaults to the parent of `DIR`
+        #[arg(long, value_name = "FILE")]
+        luarc: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PluginsCommand {
+    /// List every registered plugin
+    List {
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+
+    /// Show the details of a single plugin, including its hooks
+    Info {
+        /// Name of the plugin
+        name: String,
+
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write the effective config to stdout
+    Dump {
+        #[arg(short, long, value_enum, default_value_t)]
+        format: DumpFormat,
+    },
+    /// Write the JSON Schema of the json and toml config dumps to stdout
+    Schema,
+    /// Show every write to a config key and where it came from
+    Explain {
+        /// Key to explain, e.g. `paths.build`
+        key: String,
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Show every value that differs from a saved config
+    Diff {
+        /// Json or toml config to compare against, e.g. the output of `config dump --format json`
+        #[arg(long, value_name = "FILE")]
+        against: PathBuf,
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Encrypt a secret read from stdin into the secret store, for `secret{ store = "<name>" }`
+    ///
+    /// The passphrase is read from `$SLUA_SECRETS_KEY`. Does not load `init.lua`
+    Secret {
+        /// Name of the secret
+        name: String,
+        /// Store file, defaults to `secrets.json` in the config directory
+        #[arg(long, value_name = "FILE")]
+        store: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DumpFormat {
+    /// Lua assignments that can be loaded back into `config`
+    #[default]
+    Lua,
+    Json,
+    Toml,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// Json that can be consumed by other programs
+    Json,
+}
@@ -0,0 +1,6 @@
+-- Built-in configuration that is used when no `init.lua` could be found.
+--
+-- Create one of the following to replace it:
+--   $SLUA_CONFIG_HOME/init.lua
+--   $XDG_CONFIG_HOME/slua/init.lua
+--   ~/.config/slua/init.lua
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use mlua::{Function, Lua, Table};
+
+use crate::LuaExt;
+
+/// Environment variable that overrides every other config directory location
+pub const CONFIG_HOME_VAR: &str = "SLUA_CONFIG_HOME";
+
+/// Config file shared by every user of the machine, loaded before the user `init.lua`
+pub const SYSTEM_INIT: &str = "/etc/slua/init.lua";
+
+/// Name of the project-local config file, loaded after the user `init.lua`
+pub const PROJECT_FILE: &str = ".slua.lua";
+
+/// Machine-managed config inside of the config directory, written by `config:save()`
+/// and loaded before the user `init.lua`
+pub const SAVED_FILE: &str = "saved.json";
+
+/// Encrypted secret store inside of the config directory, used by `secret{ store = "name" }`
+pub const SECRETS_FILE: &str = "secrets.json";
+
+/// Config directory of a lua state, stored in the lua app data
+struct ConfigHome(PathBuf);
+
+/// Modules that are provided by the binary itself when they can not be
+/// resolved from `package.path`.
+const BUILTIN_MODULES: [(&str, &str); 2] = [
+    ("init", include_str!("defaults/init.lua")),
+    ("types.provided", include_str!("../lua/types/provided.lua")),
+];
+
+/// All locations that are checked for the config directory, in priority order.
+///
+/// 1. `$SLUA_CONFIG_HOME`
+/// 2. `$XDG_CONFIG_HOME/slua`
+/// 3. `~/.config/slua`
+pub fn candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(dir) = std::env::var_os(CONFIG_HOME_VAR).filter(|v| !v.is_empty()) {
+        candidates.push(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        candidates.push(PathBuf::from(dir).join("slua"));
+    }
+    if let Some(home) = home_dir() {
+        candidates.push(home.join(".config").join("slua"));
+    }
+    candidates
+}
+
+/// Find the first config directory candidate that exists
+pub fn config_home() -> Option<PathBuf> {
+    candidates().into_iter().find(|dir| dir.is_dir())
+}
+
+/// The system config file, if it exists
+pub fn system_init() -> Option<PathBuf> {
+    Some(PathBuf::from(SYSTEM_INIT)).filter(|file| file.is_file())
+}
+
+/// Find the project config file in `start` or the closest of its parents
+pub fn project_file(start: &Path) -> Option<PathBuf> {
+    start.ancestors().map(|dir| dir.join(PROJECT_FILE)).find(|file| file.is_file())
+}
+
+/// File the repl history is persisted to.
+///
+/// `$XDG_STATE_HOME/slua/history`, falling back to `~/.local/state/slua/history`
+pub fn history_file() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".local").join("state")))
+        .map(|dir| dir.join("slua").join("history"))
+}
+
+/// The `package.path` entries that resolve modules inside of a config directory
+pub fn package_paths(dir: &Path) -> Vec<String> {
+    vec![
+        dir.join("?.lua").display().to_string(),
+        dir.join("?").join("init.lua").display().to_string(),
+    ]
+}
+
+/// Point `package.path` at the config directory so `require` resolves
+/// `init.lua` and everything it requires from there.
+pub fn set_config_home(lua: &mut Lua, dir: &Path) {
+    let paths = package_paths(dir);
+    lua.set_paths(&paths.iter().map(String::as_str).collect::<Vec<_>>());
+    lua.set_app_data(ConfigHome(dir.to_path_buf()));
+}
+
+/// The [`SAVED_FILE`] in the config directory given to [`set_config_home`]
+pub fn saved_file(lua: &Lua) -> Option<PathBuf> {
+    lua.app_data_ref::<ConfigHome>().map(|home| home.0.join(SAVED_FILE))
+}
+
+/// The [`SECRETS_FILE`] in the config directory given to [`set_config_home`]
+pub fn secrets_file(lua: &Lua) -> Option<PathBuf> {
+    lua.app_data_ref::<ConfigHome>().map(|home| home.0.join(SECRETS_FILE))
+}
+
+/// Register the built-in modules in `package.preload` for every module that
+/// can not be found on the current `package.path`.
+///
+/// This must be called after `package.path` is final as modules found on the
+/// path always win over the built-in defaults.
+pub fn preload_defaults(lua: &Lua) -> Result<(), mlua::Error> {
+    let package = lua.globals().get::<_, Table>("package")?;
+    let searchpath = package.get::<_, Function>("searchpath")?;
+    let preload = package.get::<_, Table>("preload")?;
+
+    for (name, source) in BUILTIN_MODULES {
+        let found: Option<String> = searchpath.call((name, lua.path()))?;
+        if found.is_none() {
+            log::info!("[\x1b[31mRUST\x1b[39m] Using built-in {name}.lua");
+            preload.set(
+                name,
+                lua.load(source).set_name(format!("=[builtin] {name}")).into_function()?,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
@@ -1,6 +1,15 @@
+// Allows `#[derive(LuaConfig)]` to refer to `::slua` inside of this crate
+extern crate self as slua;
+
 pub mod modules;
 pub mod prelude;
 pub mod lua;
+pub mod discovery;
+pub mod runtime;
+pub mod repl;
+pub mod check;
+pub mod types;
+pub mod watch;
 
 use mlua::{Error as LuaError, Lua, Table};
 
@@ -1,5 +1,6 @@
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 use mlua::FromLuaMulti;
 use mlua::Function;
@@ -8,6 +9,7 @@ use mlua::IntoLuaMulti;
 use mlua::Lua;
 use mlua::Table;
 use mlua::Error as LuaError;
+use serde::Serialize;
 
 use crate::modules::pformat;
 use crate::modules::Import;
@@ -21,6 +23,60 @@ pub use crate::lua_pairs as __pairs;
 
 pub const NIL: mlua::Value = mlua::Value::Nil;
 
+/// A line inside of a lua chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub chunk: String,
+    pub line: usize,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.chunk, self.line)
+    }
+}
+
+/// Location of the closest lua function on the call stack.
+///
+/// Rust callbacks, C functions such as `pcall` and mlua's internal `__index`/`__newindex`
+/// chunks are skipped so this points at the lua code that triggered the current rust callback.
+pub fn caller_location(lua: &Lua) -> Option<Location> {
+    let mut level = 0;
+    while let Some(debug) = lua.inspect_stack(level) {
+        level += 1;
+
+        let line = debug.curr_line();
+        let source = debug.source();
+        if line <= 0 || source.source.as_deref().is_some_and(|s| s.starts_with("__mlua")) {
+            continue;
+        }
+        return Some(Location {
+            chunk: source.short_src.map(|s| s.to_string()).unwrap_or("?".into()),
+            line: line as usize,
+        });
+    }
+    None
+}
+
+/// File of the closest lua function on the call stack that was loaded from a file.
+///
+/// Skips the same frames as [`caller_location`]. `None` when the calling code was not
+/// loaded from a file, e.g. the repl or a string chunk.
+pub fn caller_file(lua: &Lua) -> Option<PathBuf> {
+    let mut level = 0;
+    while let Some(debug) = lua.inspect_stack(level) {
+        level += 1;
+
+        let source = debug.source();
+        let Some(source) = source.source.as_deref() else { continue };
+        if debug.curr_line() <= 0 || source.starts_with("__mlua") {
+            continue;
+        }
+        return source.strip_prefix('@').map(PathBuf::from);
+    }
+    None
+}
+
 pub trait IntoLuaEntry<'lua, R, L = ()> {
     fn into_lua_entry(self, lua: &'lua Lua) -> Result<mlua::Value<'lua>, mlua::Error>;
 }
@@ -101,15 +157,57 @@ impl<'a, F: LuaFmt<'a>> LuaFmt<'a> for &F {
     }
 }
 
+/// Lua keywords, which can not be used as names
+const KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Whether `name` can be written as `t.name` or `{ name = ... }` in lua
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+/// Key of a table constructor field: `name` for identifiers, `["name"]` otherwise
+pub fn table_key(name: &str) -> String {
+    match is_identifier(name) {
+        true => name.to_string(),
+        false => format!("[{}]", quote(name)),
+    }
+}
+
+/// Quote a string as a lua string literal, escaping anything that would
+/// otherwise end the literal or not survive being loaded back
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 impl<'a> LuaFmt<'a> for String {
     fn lua_fmt(&self, _: bool, _: usize) -> String {
-        format!("\"{}\"", self)
+        quote(self)
     }
 }
 
 impl<'a> LuaFmt<'a> for &'a str {
     fn lua_fmt(&self, _: bool, _: usize) -> String {
-        format!("\"{}\"", self)
+        quote(self)
     }
 }
 
@@ -121,7 +219,13 @@ impl<'a> LuaFmt<'a> for bool {
 
 impl<'a> LuaFmt<'a> for PathBuf {
     fn lua_fmt(&self, _: bool, _: usize) -> String {
-        format!("\"{}\"", self.display())
+        quote(&self.display().to_string())
+    }
+}
+
+impl<'a, T: LuaFmt<'a>> LuaFmt<'a> for Arc<Mutex<T>> {
+    fn lua_fmt(&self, pretty: bool, indent: usize) -> String {
+        self.lock().unwrap().lua_fmt(pretty, indent)
     }
 }
 
@@ -223,7 +327,7 @@ impl Display for LuaStructFormat {
         let spacing = if self.pretty { "  " } else { "" };
         write!(f, 
             "{{{nl}{spacing}{}{nl}}}",
-            self._s.iter().map(|(k, v)| format!("{k} = {v}")).collect::<Vec<String>>().join(format!(",{nl}{spacing}").as_str()),
+            self._s.iter().map(|(k, v)| format!("{} = {v}", table_key(k))).collect::<Vec<String>>().join(format!(",{nl}{spacing}").as_str()),
         )
     }
 }
@@ -1,54 +1,257 @@
 extern crate slua;
 
+mod cli;
+
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand, DumpFormat, OutputFormat, PluginsCommand, TypesCommand};
+use color_eyre::eyre::eyre;
 use mlua::Lua;
 use slua::{
-    modules::{Plugins, Prettify, config::Config},
-    prelude::*, LuaExt,
-    lua as _lua
+    modules::{Plugin, Plugins, config::{self, Config, LuaConfig}},
+    LuaExt, check, discovery, runtime, types,
+    repl::Repl,
+    watch::Watcher,
+    lua::{self as _lua, LuaPrint},
 };
 
 fn main() -> color_eyre::Result<()> {
-    env_logger::init();
+    let cli = Cli::parse();
+
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = cli.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
+
+    let lua = environment(&cli)?;
+
+    match &cli.command {
+        None => init(&lua, &cli)?,
+        Some(Command::Run { script, args }) => {
+            runtime::load_config_on_require(&lua, &cli.init)?;
+            runtime::run_script(&lua, script, args)?;
+            if !runtime::is_loaded(&lua, &cli.init)? {
+                if let Some(profile) = selected_profile(&cli) {
+                    eprintln!("warning: profile {profile:?} was not applied, the script did not require {:?}", cli.init);
+                }
+                let env = std::env::vars().filter_map(|(var, _)| config::env_key(&var).map(|_| var));
+                let overrides = cli.overrides.iter().map(|(key, _)| format!("--set {key}")).chain(env).collect::<Vec<_>>();
+                if !overrides.is_empty() {
+                    eprintln!("warning: {} were not applied, the script did not require {:?}", overrides.join(", "), cli.init);
+                }
+            }
+        }
+        Some(Command::Repl { history, no_history }) => {
+            // Not frozen, the repl is where config changes are tried out
+            runtime::load_config(&lua, &cli.init)?;
+
+            let mut repl = Repl::new(&lua)?;
+            if !no_history {
+                if let Some(history) = history.clone().or_else(discovery::history_file) {
+                    repl = repl.with_history(history);
+                }
+            }
+            repl.run()?;
+        }
+        Some(Command::Check) => {
+            let diagnostics = check::check_init(&lua, &cli.init);
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{diagnostic}");
+            }
+            if !diagnostics.is_empty() {
+                eprintln!("{} problem(s) found", diagnostics.len());
+                std::process::exit(1);
+            }
+            println!("No problems found");
+        }
+        Some(Command::Plugins { command }) => {
+            runtime::with_stderr_print(&lua, || require_init(&lua, &cli))?;
+
+            let plugins = Plugins::get_plugins(&lua)?;
+            match command {
+                PluginsCommand::List { format: OutputFormat::Json } => {
+                    println!("{}", serde_json::to_string_pretty(&plugins)?);
+                }
+                PluginsCommand::List { format: OutputFormat::Text } => print_plugins(&plugins),
+                PluginsCommand::Info { name, format } => {
+                    let Some(plugin) = plugins.iter().find(|plugin| &plugin.name == name) else {
+                        return Err(eyre!("no plugin named {name:?} is registered"));
+                    };
+                    match format {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(plugin)?),
+                        OutputFormat::Text => {
+                            print_plugins(std::slice::from_ref(plugin));
+                            println!("  hooks: {}", plugin.hook_names().join(", "));
+                        }
+                    }
+                }
+            }
+        }
+        Some(Command::Config { command }) => {
+            // Every command but `secret` reads the config, `secret` works even when init.lua fails to load
+            let load = || -> color_eyre::Result<Config> {
+                runtime::with_stderr_print(&lua, || require_init(&lua, &cli))?;
+                Ok(lua.globals().get::<_, Config>("config")?)
+            };
+
+            match command {
+                ConfigCommand::Secret { name, store } => {
+                    let Some(path) = store.clone().or_else(|| discovery::secrets_file(&lua)) else {
+                        return Err(eyre!("no config directory found for the secret store, pass --store"));
+                    };
+                    let mut value = String::new();
+                    std::io::stdin().read_line(&mut value)?;
+
+                    let mut secrets = config::SecretStore::open(&path)?;
+                    secrets.set(name, value.trim_end_matches(['\n', '\r']))?;
+                    secrets.save()?;
+                    println!("Stored {name} in {}", path.display());
+                }
+                ConfigCommand::Dump { format } => match (format, load()?.with_defaults(&lua)) {
+                    (DumpFormat::Lua, config) => print!("{}", config.to_lua_chunk()),
+                    (DumpFormat::Json, config) => println!("{}", serde_json::to_string_pretty(&config.with_absolute_paths())?),
+                    (DumpFormat::Toml, config) => print!("{}", toml::to_string_pretty(&config.with_absolute_paths())?),
+                },
+                ConfigCommand::Diff { against, format } => {
+                    let config = load()?;
+                    let saved = Config::load(against)?;
+                    let differences = config::diff(&saved.with_defaults(&lua).snapshot(), &config.with_defaults(&lua).snapshot());
+                    match format {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&differences)?),
+                        OutputFormat::Text if differences.is_empty() => println!("No changes"),
+                        OutputFormat::Text => {
+                            for difference in differences.iter() {
+                                println!("{difference}");
+                            }
+                        }
+                    }
+                }
+                ConfigCommand::Schema => {
+                    load()?;
+                    println!("{}", serde_json::to_string_pretty(&Config::schema(&lua))?);
+                }
+                ConfigCommand::Explain { key, format } => {
+                    load()?;
+                    if !Config::keys(&lua).contains(key) {
+                        return Err(eyre!("unknown config key {key:?}"));
+                    }
+                    let history = config::history(&lua, key);
+                    match format {
+                        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&history)?),
+                        OutputFormat::Text => {
+                            let value = lua.load(format!("return config.{key}")).eval::<mlua::Value>()?;
+                            let value = match &value {
+                                mlua::Value::String(s) => _lua::quote(s.to_str()?),
+                                value => value.printable_value()?,
+                            };
+                            println!("config.{key} = {value}");
+                            if history.is_empty() {
+                                println!("  default");
+                            }
+                            for origin in history.iter() {
+                                println!("  {origin}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Some(Command::Watch) => {
+            let Some(dir) = config_dir(&cli) else {
+                return Err(eyre!("no config directory found to watch"));
+            };
+
+            let watcher = Watcher::new(&lua, dir, cli.init.clone());
+            if let Err(err) = require_init(&lua, &cli) {
+                eprintln!("error: {err}");
+            }
+            watcher.run(|result| match result {
+                Ok(()) => println!("Reloaded {}.lua", cli.init),
+                Err(err) => eprintln!("error: {err}"),
+            })?;
+        }
+        Some(Command::Types { command: TypesCommand::Generate { dir, luarc } }) => {
+            for path in runtime::type_registry(&lua).generate(dir)? {
+                println!("Generated {}", path.display());
+            }
+
+            let luarc = luarc.clone().unwrap_or_else(|| {
+                dir.parent().unwrap_or(Path::new(".")).join(".luarc.json")
+            });
+            types::write_luarc(&luarc, dir)?;
+            println!("Updated {}", luarc.display());
+        }
+    }
+
+    Ok(())
+}
 
+/// Create the lua state with `package.path` pointing at the config directory
+/// and the standard modules loaded.
+fn environment(cli: &Cli) -> Result<Lua, mlua::Error> {
     let mut lua = Lua::new();
+    config::set_strict(&lua, cli.strict);
+    config::set_overrides(&lua, cli.overrides.clone());
+    config::set_profile(&lua, selected_profile(cli));
 
-    lua.set_paths(&[
-        "D:/Repo/Rust/scripting/lua/?.lua",
-        "D:/Repo/Rust/scripting/lua/?/init.lua",
-    ]);
+    let lua_paths = cli.lua_paths.iter().map(String::as_str).collect::<Vec<_>>();
+    match config_dir(cli) {
+        Some(dir) => {
+            log::info!("[\x1b[31mRUST\x1b[39m] Using config directory {}", dir.display());
+            discovery::set_config_home(&mut lua, &dir);
+            lua.append_paths(&lua_paths);
+        }
+        None => {
+            log::info!("[\x1b[31mRUST\x1b[39m] No config directory found");
+            // Lua's default path would pick up an init.lua from the current directory instead of the built-in one
+            lua.set_paths(&lua_paths);
+        }
+    }
+    discovery::preload_defaults(&lua)?;
 
-    lua.require::<Plugins>()?;
-    lua.import("v", _lua::module! { [lua]
-        "print" => Prettify::pprint,
-    }?)?;
+    runtime::load_std(&lua)?;
+    Ok(lua)
+}
 
-    let _ = _lua::array! { [lua]
-        Config::default(),
-    };
+/// The `--profile` or the `SLUA_PROFILE` profile
+fn selected_profile(cli: &Cli) -> Option<String> {
+    cli.profile.clone().or_else(|| std::env::var(config::PROFILE_ENV).ok().filter(|p| !p.is_empty()))
+}
 
-    lua.globals().set("config", Config::default())?;
+/// The `--config-dir` or the discovered config directory
+fn config_dir(cli: &Cli) -> Option<PathBuf> {
+    cli.config_dir.clone().or_else(discovery::config_home)
+}
 
-    log::info!("[\x1b[31mRUST\x1b[39m] Loading provided.lua");
-    lua.load("require 'types.provided'").eval()?;
+/// Load init.lua file, along with the system and project config layers. The init file and all
+/// requires should be using provided functions to load and manipulate lua state. Then the rust
+/// side will read that state and execute actions based the state.
+fn require_init(lua: &Lua, cli: &Cli) -> Result<(), mlua::Error> {
+    runtime::load_config(lua, &cli.init)?;
+    Config::freeze(lua);
+    Ok(())
+}
 
-    // Load init.lua file. The init file and all requires should be using provided functions
-    // to load and manipulate lua state. Then the rust side will read that state and execute
-    // actions based the state.
-    log::info!("[\x1b[31mRUST\x1b[39m] Loading init.lua");
-    lua.load("require 'init'").eval()?;
+fn init(lua: &Lua, cli: &Cli) -> Result<(), mlua::Error> {
+    require_init(lua, cli)?;
 
     _lua::print!(
         lua.globals().get::<_, Config>("config").unwrap(),
     );
 
     log::info!("[\x1b[31mRUST\x1b[39m] Loading plugins");
-    let plugins = Plugins::get_plugins(&lua)?;
+    print_plugins(&Plugins::get_plugins(lua)?);
+
+    Ok(())
+}
+
+fn print_plugins(plugins: &[Plugin]) {
     for plugin in plugins.iter() {
         println!(
             "{} {} by {}\n  {}",
             plugin.name, plugin.version, plugin.author, plugin.description
         );
     }
-
-    Ok(())
 }
@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+
+use mlua::{FromLua, IntoLua, Lua, LuaSerdeExt, Table, UserData, UserDataFields, UserDataMethods, Value};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::layer::history;
+use super::value::{
+    add_methods, assign, config_error, is_frozen, is_strict, key, method_definitions, schema_object, warn, ConfigValue, LuaConfig,
+};
+use crate::check::Diagnostics;
+use crate::lua::{is_identifier, quote, LuaFmt, LuaStructFormat};
+use crate::types::{Class, Field, LuaClass};
+
+/// Value of a feature flag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FlagValue {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+}
+
+impl FlagValue {
+    /// Lua type name of the value
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            FlagValue::Boolean(_) => "boolean",
+            FlagValue::Integer(_) | FlagValue::Number(_) => "number",
+            FlagValue::String(_) => "string",
+        }
+    }
+
+    /// Whether a lua value can be stored in a flag with the same type as `self`
+    fn accepts(&self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (FlagValue::Boolean(_), Value::Boolean(_))
+                | (FlagValue::Integer(_) | FlagValue::Number(_), Value::Integer(_) | Value::Number(_))
+                | (FlagValue::String(_), Value::String(_))
+        )
+    }
+}
+
+impl<'a> LuaFmt<'a> for FlagValue {
+    fn lua_fmt(&self, _: bool, _: usize) -> String {
+        match self {
+            FlagValue::Boolean(b) => b.to_string(),
+            FlagValue::Integer(i) => i.to_string(),
+            FlagValue::Number(n) => format!("{n:?}"),
+            FlagValue::String(s) => quote(s),
+        }
+    }
+}
+
+impl ConfigValue for FlagValue {
+    fn lua_type() -> String {
+        "boolean|number|string".into()
+    }
+
+    fn json_schema(_: &Lua) -> serde_json::Value {
+        json!({ "type": ["boolean", "number", "string"] })
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::Boolean(_) | Value::Integer(_) | Value::Number(_) | Value::String(_))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        match self {
+            FlagValue::Boolean(b) => b.into_lua(lua),
+            FlagValue::Integer(i) => i.into_lua(lua),
+            FlagValue::Number(n) => n.into_lua(lua),
+            FlagValue::String(s) => s.as_str().into_lua(lua),
+        }
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, _: &'lua Lua) -> mlua::Result<Self> {
+        match value {
+            Value::Boolean(b) => Ok(FlagValue::Boolean(b)),
+            Value::Integer(i) => Ok(FlagValue::Integer(i)),
+            Value::Number(n) => Ok(FlagValue::Number(n)),
+            Value::String(s) => Ok(FlagValue::String(s.to_str()?.to_string())),
+            other => Err(mlua::Error::RuntimeError(format!(
+                "feature flags must be a boolean, number or string; was {}",
+                other.type_name()
+            ))),
+        }
+    }
+}
+
+/// A feature flag that can be set with `config.features.<name>`
+#[derive(Debug, Clone, Serialize)]
+pub struct Flag {
+    pub name: String,
+    /// Value used until the flag is set, its type is the type of the flag
+    pub default: FlagValue,
+    pub description: String,
+}
+
+impl Flag {
+    pub fn new<N: Into<String>, D: Into<String>>(name: N, default: FlagValue, description: D) -> Self {
+        Self { name: name.into(), default, description: description.into() }
+    }
+}
+
+/// Declared flags of a lua state sorted by name, stored in the lua app data.
+///
+/// Each flag is stored with whether it was declared from lua, those are forgotten by [`clear_flags`].
+#[derive(Default)]
+struct Flags(Vec<(Flag, bool)>);
+
+/// Names that can not be used for flags because they are methods of `config.features`
+const RESERVED: [&str; 6] = ["declare", "flags", "replace", "resolved", "on_change", "origin"];
+
+/// Declare a flag from rust, or update it when a flag with the same name and type exists.
+///
+/// Rust code declares its flags before the config is loaded, plugins with `config.features:declare{ ... }`.
+/// Names must be lua identifiers, and not one of the methods of `config.features`.
+pub fn declare(lua: &Lua, flag: Flag) -> mlua::Result<()> {
+    insert(lua, flag, false)
+}
+
+fn insert(lua: &Lua, flag: Flag, from_lua: bool) -> mlua::Result<()> {
+    if RESERVED.contains(&flag.name.as_str()) {
+        return Err(config_error(
+            lua,
+            format!("{} is a method of config.features and can not be used as a flag name", flag.name),
+        ));
+    }
+    if !is_identifier(&flag.name) {
+        return Err(config_error(
+            lua,
+            format!("{} is not a valid flag name; expected letters, digits and underscores", quote(&flag.name)),
+        ));
+    }
+
+    let mut flags = lua.remove_app_data::<Flags>().unwrap_or_default();
+    let result = match flags.0.binary_search_by(|(f, _)| f.name.cmp(&flag.name)) {
+        Ok(i) if flags.0[i].0.default.type_name() != flag.default.type_name() => Err(config_error(
+            lua,
+            format!(
+                "config.features.{} is already declared as a {}; was redeclared as a {}",
+                flag.name,
+                flags.0[i].0.default.type_name(),
+                flag.default.type_name()
+            ),
+        )),
+        Ok(i) => {
+            flags.0[i] = (flag, from_lua);
+            Ok(())
+        }
+        Err(i) => {
+            flags.0.insert(i, (flag, from_lua));
+            Ok(())
+        }
+    };
+    lua.set_app_data(flags);
+    result
+}
+
+/// Every declared flag, sorted by name
+pub fn flags(lua: &Lua) -> Vec<Flag> {
+    lua.app_data_ref::<Flags>()
+        .map(|flags| flags.0.iter().map(|(flag, _)| flag.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// The declared flag named `name`
+pub fn flag(lua: &Lua, name: &str) -> Option<Flag> {
+    lua.app_data_ref::<Flags>()
+        .and_then(|flags| flags.0.iter().find(|(flag, _)| flag.name == name).map(|(flag, _)| flag.clone()))
+}
+
+/// Forget every flag declared from lua, e.g. before the config is loaded again. Flags declared from rust are kept
+pub fn clear_flags(lua: &Lua) {
+    if let Some(mut flags) = lua.app_data_mut::<Flags>() {
+        flags.0.retain(|(_, from_lua)| !from_lua);
+    }
+}
+
+/// Application optional features.
+///
+/// Holds the flags that were set, which is all that is serialized and printed; every other
+/// declared flag has its default value, see [`Features::values`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Features(BTreeMap<String, FlagValue>);
+
+impl Features {
+    /// Value of a flag, its default when it was not set. `None` for flags that are not declared or set
+    pub fn get(&self, lua: &Lua, name: &str) -> Option<FlagValue> {
+        self.0.get(name).cloned().or_else(|| flag(lua, name).map(|flag| flag.default))
+    }
+
+    /// Whether a boolean flag is enabled, `false` for any other flag
+    pub fn enabled(&self, lua: &Lua, name: &str) -> bool {
+        matches!(self.get(lua, name), Some(FlagValue::Boolean(true)))
+    }
+
+    /// Set a flag from rust, without any checks
+    pub fn set<N: Into<String>>(&mut self, name: N, value: FlagValue) {
+        self.0.insert(name.into(), value);
+    }
+
+    /// Value of every declared or set flag, sorted by name
+    pub fn values(&self, lua: &Lua) -> BTreeMap<String, FlagValue> {
+        let mut values = flags(lua).into_iter().map(|flag| (flag.name, flag.default)).collect::<BTreeMap<_, _>>();
+        values.extend(self.0.clone());
+        values
+    }
+
+    /// Copy with the default of every declared flag that was not set
+    pub fn with_defaults(&self, lua: &Lua) -> Self {
+        Self(self.values(lua))
+    }
+
+    /// Problems with the flags that were set: flags that were never declared and values
+    /// that do not match the type of a flag declared after they were set
+    pub fn problems(&self, lua: &Lua) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        for (name, value) in self.0.iter() {
+            match flag(lua, name) {
+                None => problems.push((name.clone(), format!("config.features.{name} is not a declared feature flag"))),
+                Some(flag) if flag.default.type_name() != value.type_name() => problems.push((
+                    name.clone(),
+                    format!(
+                        "config.features.{name} must be a {}; was {}",
+                        flag.default.type_name(),
+                        value.type_name()
+                    ),
+                )),
+                Some(_) => {}
+            }
+        }
+        problems
+    }
+}
+
+/// Report every problem of the features in `config`, at the location that set the flag.
+///
+/// Flags may be declared by plugins after the user set them, so this runs once the whole config is loaded.
+/// Problems are diagnostics in check mode, errors in strict mode and otherwise warnings printed to stderr,
+/// so a typo in a flag name is seen without enabling logging.
+pub fn validate(lua: &Lua, features: &Features) -> mlua::Result<()> {
+    for (name, message) in features.problems(lua) {
+        let location = history(lua, &key(Features::KEY, &name)).pop().and_then(|origin| origin.location);
+        if Diagnostics::report_at(lua, location.clone(), &message) {
+            continue;
+        }
+
+        let message = match location {
+            Some(location) => format!("{location}: {message}"),
+            None => message,
+        };
+        if is_strict(lua) {
+            return Err(mlua::Error::RuntimeError(message));
+        }
+        eprintln!("warning: {message}");
+    }
+    Ok(())
+}
+
+/// Only the flags that were set
+impl<'a> LuaFmt<'a> for Features {
+    fn lua_fmt(&self, pretty: bool, indent: usize) -> String {
+        self.0
+            .iter()
+            .fold(LuaStructFormat::new(pretty, indent), |format, (name, value)| format.field(name, value))
+            .to_string()
+    }
+}
+
+impl LuaConfig for Features {
+    const NAME: &'static str = "Features";
+    const KEY: &'static str = "features";
+    const FIELDS: &'static [&'static str] = &[];
+
+    fn keys(lua: &Lua) -> Vec<String> {
+        flags(lua).into_iter().map(|flag| flag.name).collect()
+    }
+
+    /// Declared flags with their type and default, other flags are allowed but reported
+    fn json_schema(lua: &Lua) -> serde_json::Value {
+        let flags = flags(lua);
+        let fields = flags
+            .iter()
+            .map(|flag| {
+                let schema = json!({ "type": flag.default.type_name(), "default": flag.default });
+                (flag.name.as_str(), schema, flag.description.as_str())
+            })
+            .collect();
+
+        let mut schema = schema_object("Application optional features, flags are declared by rust and plugins", fields);
+        schema["additionalProperties"] = FlagValue::json_schema(lua);
+        schema
+    }
+
+    fn field_names(&self, lua: &Lua) -> Vec<String> {
+        self.values(lua).into_keys().collect()
+    }
+
+    /// Any name is accepted, flags that are never declared are reported by [`validate`]
+    fn is_field(_: &str) -> bool {
+        true
+    }
+
+    fn get_field<'lua>(&self, lua: &'lua Lua, key: &str) -> mlua::Result<Option<Value<'lua>>> {
+        match self.get(lua, key) {
+            Some(value) => value.to_lua(lua).map(Some),
+            None => Ok(Some(Value::Nil)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, lua: &'lua Lua, name: &str, value: Value<'lua>) -> mlua::Result<bool> {
+        if RESERVED.contains(&name) {
+            return Ok(false);
+        }
+        if is_frozen(lua) {
+            return Err(config_error(lua, format!("config is frozen; cannot set config.features.{name}")));
+        }
+
+        let current = self.get(lua, name);
+        if let (Some(current), Some(flag)) = (&current, flag(lua, name)) {
+            if !current.accepts(&value) {
+                let message = format!(
+                    "config.features.{name} must be a {}; was {}",
+                    flag.default.type_name(),
+                    value.type_name()
+                );
+                if Diagnostics::report(lua, &message) {
+                    return Ok(true);
+                }
+                if is_strict(lua) {
+                    return Err(config_error(lua, message));
+                }
+                warn(lua, format!("{message}, ignoring it"));
+                return Ok(true);
+            }
+        }
+
+        let mut slot = current.unwrap_or(FlagValue::Boolean(false));
+        assign(lua, &mut slot, Self::KEY, name, value)?;
+        self.0.insert(name.to_string(), slot);
+        Ok(true)
+    }
+
+    fn resolved<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let table = lua.create_table()?;
+        for (name, value) in self.values(lua) {
+            table.set(name, value.to_lua(lua)?)?;
+        }
+        Ok(table)
+    }
+
+    fn from_table<'lua>(table: &Table<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        let mut features = Features::default();
+        features.merge(table, lua)?;
+        Ok(features)
+    }
+
+    fn merge<'lua>(&mut self, table: &Table<'lua>, lua: &'lua Lua) -> mlua::Result<()> {
+        for pair in table.clone().pairs::<String, Value>() {
+            let (name, value) = pair?;
+            self.set_field(lua, &name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'lua> FromLua<'lua> for Features {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        super::value::from_lua(value, lua)
+    }
+}
+
+impl Features {
+    /// [`Features::lua_class`] with a field for every flag declared in `lua`
+    pub fn definition(lua: &Lua) -> Class {
+        let mut class = Self::lua_class();
+        let declared = flags(lua)
+            .into_iter()
+            .map(|flag| Field::new(flag.name, flag.default.type_name(), flag.description));
+        class.fields.splice(0..0, declared);
+        class
+    }
+}
+
+impl LuaClass for Features {
+    fn lua_class() -> Class {
+        let class = Class::new(Self::NAME, "Application optional features, flags are declared by rust and plugins");
+        method_definitions::<Self>(class.field("[string]", FlagValue::lua_type(), "Flags declared by plugins"))
+            .field(
+                "declare",
+                "fun(self: Features, flag: { name: string, default: boolean|number|string, description: string? })",
+                "Declare a flag, its type is the type of `default`",
+            )
+            .field(
+                "flags",
+                "fun(self: Features): { name: string, default: boolean|number|string, description: string }[]",
+                "Every declared flag, sorted by name",
+            )
+    }
+}
+
+impl UserData for Features {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(_: &mut F) {}
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        add_methods(methods);
+
+        methods.add_function("declare", |lua, (_, flag): (Value, Table)| {
+            let name = flag.get::<_, String>("name")?;
+            let default = FlagValue::from_lua_value(flag.get::<_, Value>("default")?, lua)?;
+            let description = flag.get::<_, Option<String>>("description")?.unwrap_or_default();
+            insert(lua, Flag::new(name, default, description), true)
+        });
+
+        methods.add_function("flags", |lua, _: Value| lua.to_value(&flags(lua)));
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt::Display;
+
+use mlua::Lua;
+use serde::Serialize;
+
+use crate::lua::{caller_location, Location};
+
+/// Where a config value was set, in the order the layers are loaded.
+///
+/// Later layers override the values of earlier ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layer {
+    /// Built-in default values
+    #[default]
+    Default,
+    /// `/etc/slua/init.lua`
+    System,
+    /// The machine-managed config written by `config:save()`
+    Saved,
+    /// The user `init.lua` in the config directory
+    User,
+    /// `.slua.lua` in the current directory or one of its parents
+    Project,
+    /// The profile selected with `--profile` or `SLUA_PROFILE`
+    Profile,
+    /// `SLUA_<SECTION>__<KEY>` environment variables
+    Env,
+    /// `--set KEY=VALUE` command line arguments
+    Cli,
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Layer::Default => "default",
+            Layer::System => "system",
+            Layer::Saved => "saved",
+            Layer::User => "user",
+            Layer::Project => "project",
+            Layer::Profile => "profile",
+            Layer::Env => "env",
+            Layer::Cli => "cli",
+        };
+        f.pad(name)
+    }
+}
+
+/// A write to a config key
+#[derive(Debug, Clone, Serialize)]
+pub struct Origin {
+    /// Full key, e.g. `paths.build`. Replacing a whole section records the key of the section
+    pub key: String,
+    pub layer: Layer,
+    /// Lua code that made the write, `None` for writes from rust
+    pub location: Option<Location>,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<7}", self.layer)?;
+        if let Some(location) = &self.location {
+            write!(f, " {location}")?;
+        }
+        write!(f, " config.{}", self.key)
+    }
+}
+
+impl Origin {
+    /// Whether this write changed `key` or one of the fields inside of it
+    pub fn affects(&self, key: &str) -> bool {
+        self.sets(key) || self.key.strip_prefix(key).is_some_and(|rest| rest.starts_with('.'))
+    }
+
+    /// Whether this write set `key`, either directly or by replacing a section that contains it
+    pub fn sets(&self, key: &str) -> bool {
+        self.key.is_empty()
+            || self.key == key
+            || key.strip_prefix(self.key.as_str()).is_some_and(|rest| rest.starts_with('.'))
+    }
+}
+
+/// Layer that is currently being loaded, stored in the lua app data
+struct CurrentLayer(Layer);
+
+/// Every write to the config in order, stored in the lua app data
+#[derive(Debug, Default)]
+struct Origins(Vec<Origin>);
+
+/// The layer that is currently being loaded, [`Layer::Default`] outside of the loading pipeline
+pub fn current_layer(lua: &Lua) -> Layer {
+    lua.app_data_ref::<CurrentLayer>().map(|layer| layer.0).unwrap_or_default()
+}
+
+/// Run `f` with every config write recorded as part of `layer`
+pub fn with_layer<R>(lua: &Lua, layer: Layer, f: impl FnOnce() -> R) -> R {
+    let previous = lua.set_app_data(CurrentLayer(layer));
+    let result = f();
+    match previous {
+        Some(previous) => {
+            lua.set_app_data(previous);
+        }
+        None => {
+            lua.remove_app_data::<CurrentLayer>();
+        }
+    }
+    result
+}
+
+/// Record a write to `key` in the current layer, at the location of the calling lua code
+pub fn record(lua: &Lua, key: String) {
+    let origin = Origin { key, layer: current_layer(lua), location: caller_location(lua) };
+    match lua.app_data_mut::<Origins>() {
+        Some(mut origins) => origins.0.push(origin),
+        None => {
+            lua.set_app_data(Origins(vec![origin]));
+        }
+    }
+}
+
+/// Every write that changed `key` or one of the fields inside of it, oldest first
+pub fn history(lua: &Lua, key: &str) -> Vec<Origin> {
+    lua.app_data_ref::<Origins>()
+        .map(|origins| origins.0.iter().filter(|origin| origin.affects(key)).cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Layer of the last write that set `key`, [`Layer::Default`] when it was never set
+pub fn layer_of(lua: &Lua, key: &str) -> Layer {
+    lua.app_data_ref::<Origins>()
+        .and_then(|origins| origins.0.iter().rev().find(|origin| origin.sets(key)).map(|origin| origin.layer))
+        .unwrap_or_default()
+}
+
+/// Forget every recorded write, e.g. before the config is loaded again
+pub fn clear_origins(lua: &Lua) {
+    lua.remove_app_data::<Origins>();
+}
@@ -0,0 +1,413 @@
+mod features;
+mod layer;
+mod observe;
+mod overrides;
+mod path;
+mod profile;
+mod secret;
+mod snapshot;
+mod value;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use mlua::{AnyUserData, Error as LuaError, Function, Lua, LuaSerdeExt, Table, UserDataFields, UserDataMethods, Value};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize};
+
+use crate::check::Diagnostics;
+use crate::discovery;
+use crate::lua::LuaFmt;
+use crate::types::{Class, LuaClass, MetaFile};
+
+pub use features::{clear_flags, declare, flag, flags, validate, Features, Flag, FlagValue};
+pub use layer::{clear_origins, current_layer, history, layer_of, record, with_layer, Layer, Origin};
+pub use observe::{clear_observers, flush, notify, observed, on_change};
+pub use overrides::{apply_overrides, env_key, parse_override, set_overrides, ENV_PREFIX};
+pub use path::{with_base_dir, ConfigPath};
+pub use profile::{active_profile, apply_profile, clear_profiles, define_profile, profiles, set_profile, PROFILE_ENV};
+pub use secret::{Secret, SecretSource, SecretStore, Secrets, REDACTED, SECRETS_KEY_VAR};
+pub use snapshot::{diff, Difference, Snapshot};
+pub use slua_derive::LuaConfig;
+pub use value::{
+    add_methods, assign, check_keys, config_error, field_or, from_lua, is_frozen, is_strict, is_value_key, key, method_definitions, schema_object,
+    set_frozen, set_strict, thawed, warn, with_userdata, with_userdata_mut, ConfigValue, JsonValue, LuaConfig,
+};
+
+/// Paths to search for files.
+///
+/// `~`, `$VAR` and `${VAR}` are expanded and relative paths are relative to the file that sets them.
+#[derive(Debug, Clone, Serialize, Deserialize, LuaConfig)]
+#[serde(default)]
+#[lua(key = "paths")]
+pub struct Paths {
+    /// Path to the projects directory where all your cloned repositories live
+    pub projects: ConfigPath,
+    /// Path where external dependencies should be downloaded/installed
+    pub download: ConfigPath,
+    /// Path where the build will occur
+    pub build: ConfigPath,
+}
+
+impl Paths {
+    /// Copy with every path written as its resolved path, see [`Config::save`]
+    pub fn absolute(&self) -> Self {
+        Self {
+            projects: self.projects.absolute(),
+            download: self.download.absolute(),
+            build: self.build.absolute(),
+        }
+    }
+}
+
+/// Application Configuration
+#[derive(Debug, Clone, LuaConfig)]
+#[lua(methods = Config::add_methods, fields = Config::add_fields)]
+pub struct Config {
+    pub paths: Arc<Mutex<Paths>>,
+    pub features: Arc<Mutex<Features>>,
+    pub secrets: Arc<Mutex<Secrets>>,
+}
+
+impl Config {
+    /// Lua language server definitions for the `config` global, with every feature flag declared in `lua`
+    pub fn definitions(lua: &Lua) -> MetaFile {
+        MetaFile::new("config")
+            .class(
+                Class::new("ConfigOrigin", "A write to a config value")
+                    .field("key", "string", "Key that was written, a whole section when it was replaced")
+                    .field("layer", "\"default\"|\"system\"|\"saved\"|\"user\"|\"project\"|\"profile\"|\"env\"|\"cli\"", "Config layer that was loading")
+                    .field("location", "{ chunk: string, line: integer }?", "Lua code that made the write"),
+            )
+            .class(Paths::lua_class())
+            .class(Features::definition(lua))
+            .class(Secret::lua_class())
+            .class(Secrets::lua_class())
+            .class(
+                Config::lua_class()
+                    .field(
+                        "save",
+                        "fun(self: Config, path: string?): string",
+                        "Write the config to `path` (json or toml), by default the saved config that is loaded before init.lua. Returns the path",
+                    )
+                    .field(
+                        "profile",
+                        "fun(name: string, f: fun(c: Config))",
+                        "Define a profile, `f` is called with the config when it is selected with `--profile` or `SLUA_PROFILE`",
+                    )
+                    .field("active_profile", "string?", "Name of the selected profile, read only"),
+            )
+            .global("config", "Config")
+            .global(
+                "secret",
+                "fun(source: string|{ env: string?, file: string?, store: string?, path: string? }): Secret",
+            )
+    }
+
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_function_get("active_profile", |lua, _| Ok(active_profile(lua)));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function("profile", |lua, (name, f): (String, Function)| define_profile(lua, &name, f));
+
+        methods.add_function("save", |lua, (this, path): (AnyUserData, Option<String>)| {
+            let Some(path) = path.map(PathBuf::from).or_else(|| discovery::saved_file(lua)) else {
+                return Err(config_error(lua, "no config directory to save the config to"));
+            };
+            // `slua check` has no side effects, the file is only reported
+            if Diagnostics::enabled(lua) {
+                warn(lua, format!("not saving the config to {} while checking", path.display()));
+                return Ok(path.display().to_string());
+            }
+            with_userdata(&this, Config::clone)?.save(&path)?;
+            Ok(path.display().to_string())
+        });
+    }
+
+    /// JSON Schema of the json and toml config files, e.g. those written by [`Config::save`].
+    ///
+    /// Includes every feature flag that is declared in `lua` when it is called.
+    pub fn schema(lua: &Lua) -> serde_json::Value {
+        let mut schema = Self::json_schema(lua);
+        let object = schema.as_object_mut().expect("sections are objects");
+        object.insert("$schema".into(), "https://json-schema.org/draft/2020-12/schema".into());
+        object.insert("title".into(), "slua config".into());
+        schema
+    }
+
+    /// Write the values that were set to a toml file when `path` ends with `.toml`, otherwise json.
+    ///
+    /// Paths are written resolved, so they do not depend on where the file is loaded from. Paths that
+    /// are not set and feature flags that were not set are left out, they keep following their defaults.
+    /// Secrets are left out too, they would only be written redacted.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> mlua::Result<()> {
+        let path = path.as_ref();
+        let mut values = serde_json::to_value(self.with_absolute_paths()).map_err(LuaError::external)?;
+        if let Some(values) = values.as_object_mut() {
+            values.remove(Secrets::KEY);
+        }
+        if let Some(paths) = values.get_mut(Paths::KEY).and_then(|paths| paths.as_object_mut()) {
+            paths.retain(|_, path| path != "");
+        }
+
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::to_string_pretty(&values).map_err(LuaError::external)?,
+            _ => serde_json::to_string_pretty(&values).map_err(LuaError::external)?,
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(LuaError::external)?;
+        }
+        std::fs::write(path, contents).map_err(LuaError::external)
+    }
+
+    /// Read a config written by [`Config::save`], or any json or toml file of the same shape.
+    ///
+    /// Missing values use their default. Relative paths are resolved against the current directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> mlua::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(LuaError::external)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(LuaError::external),
+            _ => serde_json::from_str(&contents).map_err(LuaError::external),
+        }
+    }
+
+    /// Merge every value of `other` into the config of the lua state, as if it was assigned from lua.
+    ///
+    /// Works on a frozen config. Paths that are not set are skipped, secrets serialize redacted so they
+    /// are copied as they are.
+    pub fn apply(lua: &Lua, other: &Config) -> mlua::Result<()> {
+        let Value::Table(table) = lua.to_value(other)? else {
+            return Err(mlua::Error::RuntimeError("config must serialize to a table".into()));
+        };
+        table.set(Secrets::KEY, Value::Nil)?;
+        let paths = table.get::<_, Table>(Paths::KEY)?;
+        for field in Paths::FIELDS {
+            if paths.get::<_, String>(*field)?.is_empty() {
+                paths.set(*field, Value::Nil)?;
+            }
+        }
+        let config = lua.globals().get::<_, AnyUserData>("config")?;
+        thawed(lua, || with_userdata_mut(&config, |config: &mut Config| config.merge(&table, lua)))??;
+        let secrets = other.secrets.lock().unwrap().clone();
+        with_userdata(&config, |config: &Config| config.secrets.lock().unwrap().extend(&secrets))?;
+        flush(lua)
+    }
+
+    /// Reject every further write from lua with a "config is frozen" error, called by the host once
+    /// `init.lua` and the plugins are loaded.
+    ///
+    /// Rust can still update the config through [`Config::apply`], [`thawed`] or the section mutexes.
+    pub fn freeze(lua: &Lua) {
+        set_frozen(lua, true);
+    }
+
+    /// Call `f` with the old and new value whenever `key` is changed from lua, e.g. by a
+    /// plugin or the user config. `key` must be a value such as `features.show_docker_logs`.
+    ///
+    /// Unlike observers registered from lua, `f` is kept when `slua watch` reloads the config.
+    pub fn on_change<F>(lua: &Lua, key: &str, f: F) -> mlua::Result<()>
+    where
+        F: for<'lua> Fn(&'lua Lua, Value<'lua>, Value<'lua>) -> mlua::Result<()> + 'static,
+    {
+        if !is_value_key::<Self>(lua, key) {
+            return Err(mlua::Error::RuntimeError(format!("config.{key} is not a config value that can be observed")));
+        }
+        on_change(lua, key, lua.create_function(move |lua, (old, new): (Value, Value)| f(lua, old, new))?, false)
+    }
+
+    /// Copy that shares nothing with this config, with the default of every feature flag declared
+    /// in `lua` that was not set. Used to print or compare the effective config
+    pub fn with_defaults(&self, lua: &Lua) -> Config {
+        Config {
+            paths: Arc::new(Mutex::new(self.paths.lock().unwrap().clone())),
+            features: Arc::new(Mutex::new(self.features.lock().unwrap().with_defaults(lua))),
+            secrets: Arc::new(Mutex::new(self.secrets.lock().unwrap().clone())),
+        }
+    }
+
+    /// Config with every path written as its resolved path, sharing the other sections with this one
+    pub fn with_absolute_paths(&self) -> Config {
+        Config {
+            paths: Arc::new(Mutex::new(self.paths.lock().unwrap().absolute())),
+            ..self.clone()
+        }
+    }
+
+    /// Plain-data copy of the current values, see [`diff`].
+    ///
+    /// Paths are resolved, so a config compares equal to its json dump or saved file. Secrets are
+    /// left out, they are redacted in every file and can not be compared.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut values = serde_json::to_value(self.with_absolute_paths()).expect("config serializes to json");
+        if let Some(values) = values.as_object_mut() {
+            values.remove(Secrets::KEY);
+        }
+        Snapshot::new(&values).expect("config serializes to a json object")
+    }
+
+    /// Lua chunk that assigns every section of the config.
+    ///
+    /// Loading the chunk with a `config` global restores the current values. Secrets are
+    /// redacted, so they are listed in a comment.
+    pub fn to_lua_chunk(&self) -> String {
+        let mut chunk = format!(
+            "config.paths = {}\nconfig.features = {}\n",
+            self.paths.lock().unwrap().lua_fmt(true, 0),
+            self.features.lock().unwrap().lua_fmt(true, 0),
+        );
+        let secrets = self.secrets.lock().unwrap();
+        if !secrets.is_empty() {
+            chunk.push_str(&format!("--[[ config.secrets = {} ]]\n", secrets.lua_fmt(true, 0)));
+        }
+        chunk
+    }
+}
+
+impl Serialize for Config {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut config = serializer.serialize_struct("Config", 3)?;
+        config.serialize_field("paths", &*self.paths.lock().unwrap())?;
+        config.serialize_field("features", &*self.features.lock().unwrap())?;
+        config.serialize_field("secrets", &*self.secrets.lock().unwrap())?;
+        config.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Default, Deserialize)]
+        #[serde(default)]
+        struct Sections {
+            paths: Paths,
+            features: Features,
+            secrets: Secrets,
+        }
+
+        let sections = Sections::deserialize(deserializer)?;
+        Ok(Config {
+            paths: Arc::new(Mutex::new(sections.paths)),
+            features: Arc::new(Mutex::new(sections.features)),
+            secrets: Arc::new(Mutex::new(sections.secrets)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, LuaConfig)]
+    #[lua(key = "test")]
+    struct Section {
+        name: String,
+        #[lua(default = "fallback")]
+        other: String,
+        #[lua(default = true)]
+        enabled: bool,
+        #[lua(skip, default = 3u32)]
+        hidden: u32,
+    }
+
+    #[test]
+    fn default_matches_empty_table() {
+        let lua = Lua::new();
+        let default = Section::default();
+        let from_table = lua.load("return {}").eval::<Section>().unwrap();
+
+        assert_eq!((default.name.as_str(), default.other.as_str(), default.enabled, default.hidden), ("", "fallback", true, 3));
+        assert_eq!(
+            (from_table.name, from_table.other, from_table.enabled, from_table.hidden),
+            (default.name, default.other, default.enabled, default.hidden)
+        );
+    }
+
+    #[test]
+    fn replace_keeps_skipped_fields() {
+        let lua = Lua::new();
+        let section = Arc::new(Mutex::new(Section { hidden: 7, ..Section::default() }));
+        lua.globals().set("section", section.clone()).unwrap();
+        lua.load("section:replace{ name = 'x' }").exec().unwrap();
+
+        let section = section.lock().unwrap();
+        assert_eq!((section.name.as_str(), section.other.as_str(), section.hidden), ("x", "fallback", 7));
+    }
+
+    #[test]
+    fn table_assignment_merges_and_replace_resets() {
+        let lua = Lua::new();
+        let config = Config::default();
+        lua.globals().set("config", config.clone()).unwrap();
+        lua.load("config.paths.build = 'a'; config.paths = { download = 'd' }").exec().unwrap();
+        {
+            let paths = config.paths.lock().unwrap();
+            assert_eq!((paths.build.as_str(), paths.download.as_str()), ("a", "d"));
+        }
+
+        lua.load("config.paths:replace{ build = 'b' }").exec().unwrap();
+        let paths = config.paths.lock().unwrap();
+        assert_eq!((paths.build.as_str(), paths.download.as_str()), ("b", ""));
+    }
+
+    #[test]
+    fn held_reference_sees_new_values() {
+        let lua = Lua::new();
+        lua.globals().set("config", Config::default()).unwrap();
+        let seen = lua
+            .load(
+                r#"
+                local paths = config.paths
+                config.paths = { build = "a" }
+                local merged = paths.build
+                config:replace{ paths = { build = "b" } }
+                return merged, paths.build
+                "#,
+            )
+            .eval::<(String, String)>()
+            .unwrap();
+        assert_eq!(seen, ("a".to_string(), "b".to_string()));
+    }
+
+    #[test]
+    fn frozen_config_rejects_lua_assignments() {
+        let lua = Lua::new();
+        let config = Config::default();
+        lua.globals().set("config", config.clone()).unwrap();
+        lua.load("config.paths.build = 'a'").exec().unwrap();
+        Config::freeze(&lua);
+
+        for (code, key) in [
+            ("config.paths.build = 'x'", "paths.build"),
+            ("config.paths = { build = 'x' }", "paths"),
+            ("config.paths:replace{}", "paths"),
+            ("config.features.x = true", "features.x"),
+        ] {
+            let err = lua.load(code).exec().unwrap_err().to_string();
+            assert!(err.contains(&format!("config is frozen; cannot set config.{key}")), "{code}: {err}");
+        }
+        assert_eq!(config.paths.lock().unwrap().build.as_str(), "a");
+
+        let other = Config::default();
+        other.paths.lock().unwrap().build = ConfigPath::new("b", None);
+        Config::apply(&lua, &other).unwrap();
+        assert_eq!(config.paths.lock().unwrap().build.as_str(), "b");
+    }
+
+    #[test]
+    fn lua_chunk_loads_back() {
+        let config = Config::default();
+        config.paths.lock().unwrap().build = ConfigPath::new("out", Some(Path::new("/base")));
+        config.features.lock().unwrap().set("my-flag", FlagValue::Boolean(true));
+        config.features.lock().unwrap().set("end", FlagValue::Integer(2));
+        let chunk = config.to_lua_chunk();
+        assert!(chunk.contains(r#"["my-flag"] = true"#), "{chunk}");
+
+        let lua = Lua::new();
+        lua.globals().set("config", Config::default()).unwrap();
+        lua.load(&chunk).exec().unwrap();
+        let loaded = lua.globals().get::<_, Config>("config").unwrap();
+        assert_eq!(loaded.paths.lock().unwrap().build.as_str(), "out");
+        assert_eq!(*loaded.features.lock().unwrap(), *config.features.lock().unwrap());
+    }
+}
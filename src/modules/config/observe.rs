@@ -0,0 +1,162 @@
+use mlua::{Function, Lua, RegistryKey, Value};
+
+use crate::check::Diagnostics;
+
+/// Functions called when a config value changes, stored in the lua app data
+#[derive(Default)]
+struct Observers(Vec<Observer>);
+
+struct Observer {
+    key: String,
+    f: RegistryKey,
+    /// Registered with `on_change` from lua, forgotten by [`clear_observers`]
+    from_lua: bool,
+}
+
+/// A change to an observed key that was not passed to the observers yet
+struct Change {
+    key: String,
+    old: RegistryKey,
+    new: RegistryKey,
+}
+
+/// Changes waiting for [`flush`], stored in the lua app data
+struct Pending(Vec<Change>);
+
+/// Call `f` with the old and new value whenever `key` is changed.
+///
+/// `key` must be a value, e.g. `features.show_docker_logs`, and not a section.
+/// Observers are called for writes from lua, including merged tables and replaced sections.
+/// `from_lua` is set for observers registered by lua code, which are removed by [`clear_observers`].
+pub fn on_change<'lua>(lua: &'lua Lua, key: &str, f: Function<'lua>, from_lua: bool) -> mlua::Result<()> {
+    let observer = Observer { key: key.to_string(), f: lua.create_registry_value(f)?, from_lua };
+    match lua.app_data_mut::<Observers>() {
+        Some(mut observers) => observers.0.push(observer),
+        None => {
+            lua.set_app_data(Observers(vec![observer]));
+        }
+    }
+    Ok(())
+}
+
+/// Remove every observer registered from lua, e.g. before the config is loaded again.
+/// Observers registered from rust with [`Config::on_change`](super::Config::on_change) are kept
+pub fn clear_observers(lua: &Lua) {
+    let Some(observers) = lua.remove_app_data::<Observers>() else { return };
+    let (removed, kept) = observers.0.into_iter().partition::<Vec<_>, _>(|observer| observer.from_lua);
+    for observer in removed {
+        let _ = lua.remove_registry_value(observer.f);
+    }
+    lua.set_app_data(Observers(kept));
+    lua.expire_registry_values();
+}
+
+/// Observed keys that are `key` or inside of it.
+///
+/// Always empty in check mode, so checking a config never calls observers.
+pub fn observed(lua: &Lua, key: &str) -> Vec<String> {
+    if Diagnostics::enabled(lua) {
+        return Vec::new();
+    }
+
+    let mut keys = lua
+        .app_data_ref::<Observers>()
+        .map(|observers| {
+            observers
+                .0
+                .iter()
+                .map(|observer| &observer.key)
+                .filter(|observed| relative(key, observed).is_some())
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Queue a call to the observers of every key in `keys` whose value differs between `old` and `new`.
+///
+/// `old` and `new` are the values of `key` before and after the write; when `key` is a
+/// section the values of the observed keys are looked up inside of it.
+/// The observers are called by [`flush`], once the config is no longer borrowed by the write.
+pub fn notify<'lua>(lua: &'lua Lua, key: &str, keys: &[String], old: Value<'lua>, new: Value<'lua>) -> mlua::Result<()> {
+    let mut changes = Vec::new();
+    for observed in keys {
+        let Some(path) = relative(key, observed) else { continue };
+        let old = lookup(lua, old.clone(), path)?;
+        let new = lookup(lua, new.clone(), path)?;
+        if !old.equals(&new)? {
+            changes.push(Change {
+                key: observed.clone(),
+                old: lua.create_registry_value(old)?,
+                new: lua.create_registry_value(new)?,
+            });
+        }
+    }
+
+    match lua.app_data_mut::<Pending>() {
+        Some(mut pending) => pending.0.extend(changes),
+        None => {
+            lua.set_app_data(Pending(changes));
+        }
+    }
+    Ok(())
+}
+
+/// Call the observers of every change queued by [`notify`].
+///
+/// Called after every write from lua; rust code that writes with [`LuaConfig::set_field`](super::LuaConfig::set_field)
+/// should call it once it is done.
+pub fn flush(lua: &Lua) -> mlua::Result<()> {
+    let Some(pending) = lua.remove_app_data::<Pending>() else { return Ok(()) };
+    for change in pending.0 {
+        let old = lua.registry_value::<Value>(&change.old)?;
+        let new = lua.registry_value::<Value>(&change.new)?;
+        lua.remove_registry_value(change.old)?;
+        lua.remove_registry_value(change.new)?;
+
+        // Collected first, an observer may register more observers
+        let functions = lua
+            .app_data_ref::<Observers>()
+            .map(|observers| {
+                observers
+                    .0
+                    .iter()
+                    .filter(|observer| observer.key == change.key)
+                    .map(|observer| lua.registry_value::<Function>(&observer.f))
+                    .collect::<mlua::Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        for f in functions {
+            f.call::<_, ()>((old.clone(), new.clone()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Path of `observed` relative to `key`, `Some("")` when they are the same key
+fn relative<'a>(key: &str, observed: &'a str) -> Option<&'a str> {
+    if key.is_empty() {
+        return Some(observed);
+    }
+    match observed.strip_prefix(key)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('.'),
+    }
+}
+
+/// Index `value` with every part of a dotted `path`
+fn lookup<'lua>(lua: &'lua Lua, mut value: Value<'lua>, path: &str) -> mlua::Result<Value<'lua>> {
+    if path.is_empty() {
+        return Ok(value);
+    }
+
+    let index = lua.load("local value, key = ...\nreturn value[key]").into_function()?;
+    for part in path.split('.') {
+        value = index.call((value, part))?;
+    }
+    Ok(value)
+}
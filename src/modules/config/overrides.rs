@@ -0,0 +1,170 @@
+use mlua::{Lua, Value};
+use serde_json::Value as JsonValue;
+
+use super::layer::{with_layer, Layer};
+use super::observe::flush;
+use super::value::LuaConfig;
+use super::Config;
+
+/// Prefix of environment variables that override config values, e.g. `SLUA_PATHS__BUILD`
+pub const ENV_PREFIX: &str = "SLUA_";
+
+/// `--set` overrides given on the command line, stored in the lua app data
+struct Overrides(Vec<(String, String)>);
+
+/// Config key of an override environment variable: `SLUA_PATHS__BUILD` is `paths.build`.
+///
+/// `None` for variables without the prefix or a `__` separator, e.g. `SLUA_CONFIG_HOME`.
+pub fn env_key(var: &str) -> Option<String> {
+    let key = var.strip_prefix(ENV_PREFIX)?;
+    key.contains("__").then(|| key.to_lowercase().replace("__", "."))
+}
+
+/// Parse a `KEY=VALUE` override
+pub fn parse_override(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE; was {arg:?}")),
+    }
+}
+
+/// Set the `--set` overrides that are applied after every config layer is loaded
+pub fn set_overrides(lua: &Lua, overrides: Vec<(String, String)>) {
+    lua.set_app_data(Overrides(overrides));
+}
+
+/// Apply every override environment variable, then the `--set` overrides.
+///
+/// Called after every config layer is loaded. Writes are recorded in [`Layer::Env`] and [`Layer::Cli`].
+pub fn apply_overrides(lua: &Lua) -> mlua::Result<()> {
+    let mut env = std::env::vars()
+        .filter_map(|(var, value)| env_key(&var).map(|key| (var, key, value)))
+        .collect::<Vec<_>>();
+    env.sort();
+    with_layer(lua, Layer::Env, || {
+        env.iter().try_for_each(|(var, key, value)| apply(lua, var, key, value))
+    })?;
+
+    let overrides = lua.app_data_ref::<Overrides>().map(|o| o.0.clone()).unwrap_or_default();
+    with_layer(lua, Layer::Cli, || {
+        overrides.iter().try_for_each(|(key, value)| apply(lua, &format!("--set {key}"), key, value))
+    })
+}
+
+/// Parse `value` to the type of `key` and assign it to the config, as if it was set from lua.
+///
+/// `source` names the override in errors and in the origin of the write.
+pub fn apply(lua: &Lua, source: &str, key: &str, value: &str) -> mlua::Result<()> {
+    let error = |message: String| mlua::Error::RuntimeError(format!("{source}: {message}"));
+
+    let value = match value_type(lua, key).map_err(error)? {
+        Some(ty) => parse(lua, &ty, value).map_err(error)?,
+        None => infer(lua, value)?,
+    };
+
+    let keys = key.split('.').collect::<Vec<_>>();
+    // A single line, so the origin of the write is `<source>:1`
+    lua.load("local t, keys, value = config, ... for i = 1, #keys - 1 do t = t[keys[i]] end t[keys[#keys]] = value")
+        .set_name(format!("={source}"))
+        .call::<_, ()>((keys, value))?;
+    flush(lua)
+}
+
+/// Json schema type of the value at `key`, `None` when any type is allowed
+fn value_type(lua: &Lua, key: &str) -> Result<Option<String>, String> {
+    let mut schema = Config::json_schema(lua);
+    for part in key.split('.') {
+        let field = schema.get("properties").and_then(|properties| properties.get(part)).cloned();
+        schema = match (field, schema.get("additionalProperties")) {
+            (Some(field), _) => field,
+            (None, Some(additional)) if additional.is_object() => additional.clone(),
+            _ => return Err(format!("config.{key} is not a valid key")),
+        };
+    }
+
+    match schema.get("type") {
+        Some(JsonValue::String(ty)) if ty == "object" => Err(format!("config.{key} is a section, set one of its keys")),
+        Some(JsonValue::String(ty)) => Ok(Some(ty.clone())),
+        _ => Ok(None),
+    }
+}
+
+fn parse<'lua>(lua: &'lua Lua, ty: &str, value: &str) -> Result<Value<'lua>, String> {
+    let invalid = || format!("expected a {ty}; was {value:?}");
+    Ok(match ty {
+        "boolean" => match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Value::Boolean(true),
+            "false" | "0" | "no" | "off" => Value::Boolean(false),
+            _ => return Err(invalid()),
+        },
+        "number" | "integer" => match value.parse::<i64>() {
+            Ok(i) => Value::Integer(i),
+            Err(_) if ty == "number" => Value::Number(finite(value).ok_or_else(invalid)?),
+            Err(_) => return Err(invalid()),
+        },
+        _ => Value::String(lua.create_string(value).map_err(|e| e.to_string())?),
+    })
+}
+
+/// Value of a key that allows any type, e.g. a feature flag that is not declared
+fn infer<'lua>(lua: &'lua Lua, value: &str) -> mlua::Result<Value<'lua>> {
+    Ok(match value {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        value => match (value.parse::<i64>(), finite(value)) {
+            (Ok(i), _) => Value::Integer(i),
+            (_, Some(n)) => Value::Number(n),
+            _ => Value::String(lua.create_string(value)?),
+        },
+    })
+}
+
+/// Parse a number, `None` for `nan` and `inf` which are not valid config values
+fn finite(value: &str) -> Option<f64> {
+    value.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_key_maps_sections_and_keys() {
+        assert_eq!(env_key("SLUA_PATHS__BUILD").as_deref(), Some("paths.build"));
+        assert_eq!(env_key("SLUA_FEATURES__SHOW_DOCKER_LOGS").as_deref(), Some("features.show_docker_logs"));
+        assert_eq!(env_key("SLUA_CONFIG_HOME"), None);
+        assert_eq!(env_key("PATHS__BUILD"), None);
+    }
+
+    #[test]
+    fn parse_override_splits_on_the_first_equals() {
+        assert_eq!(parse_override("paths.build=a=b"), Ok(("paths.build".to_string(), "a=b".to_string())));
+        assert_eq!(parse_override(" features.x =").map(|(key, _)| key), Ok("features.x".to_string()));
+        assert!(parse_override("paths.build").is_err());
+        assert!(parse_override("=value").is_err());
+    }
+
+    #[test]
+    fn parse_uses_the_schema_type() {
+        let lua = Lua::new();
+        assert!(matches!(parse(&lua, "boolean", "yes"), Ok(Value::Boolean(true))));
+        assert!(matches!(parse(&lua, "boolean", "OFF"), Ok(Value::Boolean(false))));
+        assert!(parse(&lua, "boolean", "maybe").is_err());
+        assert!(matches!(parse(&lua, "integer", "42"), Ok(Value::Integer(42))));
+        assert!(parse(&lua, "integer", "1.5").is_err());
+        assert!(matches!(parse(&lua, "number", "1.5"), Ok(Value::Number(n)) if n == 1.5));
+        assert!(parse(&lua, "number", "nan").is_err());
+        assert!(parse(&lua, "number", "inf").is_err());
+        assert!(matches!(parse(&lua, "string", "42"), Ok(Value::String(s)) if s == "42"));
+    }
+
+    #[test]
+    fn infer_keeps_non_finite_numbers_as_strings() {
+        let lua = Lua::new();
+        assert!(matches!(infer(&lua, "true"), Ok(Value::Boolean(true))));
+        assert!(matches!(infer(&lua, "3"), Ok(Value::Integer(3))));
+        assert!(matches!(infer(&lua, "1e3"), Ok(Value::Number(n)) if n == 1000.0));
+        assert!(matches!(infer(&lua, "nan"), Ok(Value::String(s)) if s == "nan"));
+        assert!(matches!(infer(&lua, "inf"), Ok(Value::String(s)) if s == "inf"));
+    }
+}
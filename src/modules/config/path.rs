@@ -0,0 +1,230 @@
+use std::path::{Component, Path, PathBuf};
+
+use mlua::{FromLua, IntoLua, Lua, Value};
+use serde::{Deserialize, Serialize};
+
+use super::value::{config_error, is_strict, ConfigValue};
+use crate::check::Diagnostics;
+use crate::discovery::home_dir;
+use crate::lua::{caller_file, quote, LuaFmt};
+
+/// A path set from lua.
+///
+/// Keeps the value as it was written, so a dumped config still works on another machine,
+/// next to the resolved path: `~` and `$VAR`/`${VAR}` are expanded and relative paths
+/// are joined to the directory of the config file that set the value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigPath {
+    value: String,
+    resolved: PathBuf,
+}
+
+impl ConfigPath {
+    /// Resolve `value` relative to `base`, or the current directory when there is no base.
+    ///
+    /// Environment variables that are not set are kept as they were written.
+    pub fn new<S: Into<String>>(value: S, base: Option<&Path>) -> Self {
+        let value = value.into();
+        let resolved = resolve(&expand(&value).0, base);
+        Self { value, resolved }
+    }
+
+    /// The path as it was written in the config
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// The expanded and absolute path, empty when the path is not set
+    pub fn resolved(&self) -> &Path {
+        &self.resolved
+    }
+
+    /// Copy written as its resolved path, which means the same wherever it is loaded from
+    pub fn absolute(&self) -> Self {
+        Self { value: self.resolved.display().to_string(), resolved: self.resolved.clone() }
+    }
+}
+
+impl AsRef<Path> for ConfigPath {
+    fn as_ref(&self) -> &Path {
+        &self.resolved
+    }
+}
+
+impl Serialize for ConfigPath {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.value)
+    }
+}
+
+/// Relative paths are resolved against the current directory
+impl<'de> Deserialize<'de> for ConfigPath {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(String::deserialize(deserializer)?, None))
+    }
+}
+
+impl<'a> LuaFmt<'a> for ConfigPath {
+    fn lua_fmt(&self, _: bool, _: usize) -> String {
+        quote(&self.value)
+    }
+}
+
+/// Paths are exposed to lua as the string that was assigned, see `resolved()` for the final path
+impl ConfigValue for ConfigPath {
+    fn lua_type() -> String {
+        "string".into()
+    }
+
+    fn json_schema(_: &Lua) -> serde_json::Value {
+        serde_json::json!({ "type": "string" })
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::String(_))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.value.as_str().into_lua(lua)
+    }
+
+    fn resolved_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.resolved.display().to_string().into_lua(lua)
+    }
+
+    /// Relative paths are resolved against the lua file that is currently running,
+    /// or the directory given to [`with_base_dir`] for writes from rust
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        let value = String::from_lua(value, lua)?;
+        for name in expand(&value).1 {
+            let message = format!("environment variable {name} used in {} is not set", quote(&value));
+            if !Diagnostics::report(lua, &message) && is_strict(lua) {
+                return Err(config_error(lua, message));
+            }
+        }
+
+        let base = caller_file(lua)
+            .and_then(|file| file.parent().map(Path::to_path_buf))
+            .or_else(|| lua.app_data_ref::<BaseDir>().map(|dir| dir.0.clone()));
+        Ok(Self::new(value, base.as_deref()))
+    }
+}
+
+/// Directory relative paths are resolved against when no lua file is running
+struct BaseDir(PathBuf);
+
+/// Run `f` with relative paths that are not set from a lua file resolved against `dir`,
+/// e.g. while applying a config file loaded from rust
+pub fn with_base_dir<R>(lua: &Lua, dir: &Path, f: impl FnOnce() -> R) -> R {
+    let previous = lua.set_app_data(BaseDir(dir.to_path_buf()));
+    let result = f();
+    match previous {
+        Some(previous) => {
+            lua.set_app_data(previous);
+        }
+        None => {
+            lua.remove_app_data::<BaseDir>();
+        }
+    }
+    result
+}
+
+/// Expand `~` and environment variables.
+///
+/// Returns the expanded string and the names of the variables that are not set,
+/// those are left in the string as they were written.
+fn expand(value: &str) -> (String, Vec<String>) {
+    let mut missing = Vec::new();
+    let mut out = String::with_capacity(value.len());
+
+    let mut rest = value;
+    if let Some(home) = home_dir() {
+        if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+            out.push_str(&home.display().to_string());
+            rest = &rest[1..];
+        }
+    }
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+
+        let written = &rest[start..start + 1 + len];
+        match std::env::var(name) {
+            _ if name.is_empty() => out.push_str(written),
+            Ok(var) => out.push_str(&var),
+            Err(_) => {
+                missing.push(name.to_string());
+                out.push_str(written);
+            }
+        }
+        rest = &rest[start + 1 + len..];
+    }
+    out.push_str(rest);
+
+    (out, missing)
+}
+
+/// Join relative paths to `base`, then to the current directory, and drop `.` components
+fn resolve(path: &str, base: Option<&Path>) -> PathBuf {
+    if path.is_empty() {
+        return PathBuf::new();
+    }
+
+    let mut path = match base {
+        Some(base) => base.join(path),
+        None => PathBuf::from(path),
+    };
+    if path.is_relative() {
+        if let Ok(dir) = std::env::current_dir() {
+            path = dir.join(path);
+        }
+    }
+    path.components().filter(|c| !matches!(c, Component::CurDir)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_home_and_variables() {
+        std::env::set_var("SLUA_TEST_EXPAND", "/opt/slua");
+        std::env::remove_var("SLUA_TEST_MISSING");
+        let home = home_dir().expect("HOME is set").display().to_string();
+
+        assert_eq!(expand("~/src"), (format!("{home}/src"), Vec::new()));
+        assert_eq!(expand("a/~/b").0, "a/~/b");
+        assert_eq!(expand("$SLUA_TEST_EXPAND/build").0, "/opt/slua/build");
+        assert_eq!(expand("${SLUA_TEST_EXPAND}x").0, "/opt/sluax");
+        assert_eq!(
+            expand("$SLUA_TEST_MISSING/${SLUA_TEST_MISSING}"),
+            ("$SLUA_TEST_MISSING/${SLUA_TEST_MISSING}".to_string(), vec!["SLUA_TEST_MISSING".to_string(); 2])
+        );
+        assert_eq!(expand("cost: $5 ${unclosed").0, "cost: $5 ${unclosed");
+        assert_eq!(expand("$").0, "$");
+    }
+
+    #[test]
+    fn resolve_against_base_then_current_dir() {
+        let cwd = std::env::current_dir().unwrap();
+
+        assert_eq!(resolve("", Some(Path::new("/base"))), PathBuf::new());
+        assert_eq!(resolve("/abs/./path", Some(Path::new("/base"))), PathBuf::from("/abs/path"));
+        assert_eq!(resolve("./out", Some(Path::new("/base"))), PathBuf::from("/base/out"));
+        assert_eq!(resolve("out", None), cwd.join("out"));
+        assert_eq!(resolve("out", Some(Path::new("rel"))), cwd.join("rel/out"));
+    }
+}
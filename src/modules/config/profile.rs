@@ -0,0 +1,86 @@
+use mlua::{AnyUserData, Function, Lua, RegistryKey};
+
+use super::layer::{with_layer, Layer};
+use super::observe::flush;
+
+/// Environment variable that selects a profile when `--profile` is not given
+pub const PROFILE_ENV: &str = "SLUA_PROFILE";
+
+/// Profiles defined with `config.profile(name, f)`, stored in the lua app data in the order they were defined
+#[derive(Default)]
+struct Profiles(Vec<(String, RegistryKey)>);
+
+/// Profile selected at startup, stored in the lua app data
+struct ActiveProfile(String);
+
+/// Select the profile that is applied once every config file is loaded, `None` for the base config only
+pub fn set_profile(lua: &Lua, name: Option<String>) {
+    match name {
+        Some(name) => {
+            lua.set_app_data(ActiveProfile(name));
+        }
+        None => {
+            lua.remove_app_data::<ActiveProfile>();
+        }
+    }
+}
+
+/// The selected profile, known before it is applied so plugins can read it while they are loaded
+pub fn active_profile(lua: &Lua) -> Option<String> {
+    lua.app_data_ref::<ActiveProfile>().map(|profile| profile.0.clone())
+}
+
+/// Define a profile, replacing an earlier one with the same name.
+///
+/// `f` is called with the `config` userdata by [`apply_profile`] when the profile is selected.
+pub fn define_profile<'lua>(lua: &'lua Lua, name: &str, f: Function<'lua>) -> mlua::Result<()> {
+    let f = lua.create_registry_value(f)?;
+    let mut profiles = lua.remove_app_data::<Profiles>().unwrap_or_default();
+    match profiles.0.iter_mut().find(|(defined, _)| defined == name) {
+        Some((_, previous)) => lua.remove_registry_value(std::mem::replace(previous, f))?,
+        None => profiles.0.push((name.to_string(), f)),
+    }
+    lua.set_app_data(profiles);
+    Ok(())
+}
+
+/// Names of every defined profile, in the order they were defined
+pub fn profiles(lua: &Lua) -> Vec<String> {
+    lua.app_data_ref::<Profiles>()
+        .map(|profiles| profiles.0.iter().map(|(name, _)| name.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// Call the function of the selected profile with the `config` global, recording its writes in [`Layer::Profile`].
+///
+/// Does nothing when no profile is selected; selecting a profile that was never defined is an error.
+pub fn apply_profile(lua: &Lua) -> mlua::Result<()> {
+    let Some(name) = active_profile(lua) else { return Ok(()) };
+
+    let f = lua
+        .app_data_ref::<Profiles>()
+        .and_then(|profiles| profiles.0.iter().find(|(defined, _)| *defined == name).map(|(_, f)| lua.registry_value::<Function>(f)))
+        .transpose()?;
+    let Some(f) = f else {
+        let message = match profiles(lua) {
+            defined if defined.is_empty() => format!("profile {name:?} is not defined; no profiles are defined"),
+            defined => format!("profile {name:?} is not defined; expected one of: {}", defined.join(", ")),
+        };
+        return Err(mlua::Error::RuntimeError(message));
+    };
+
+    log::info!("[\x1b[31mRUST\x1b[39m] Applying profile {name}");
+    let config = lua.globals().get::<_, AnyUserData>("config")?;
+    with_layer(lua, Layer::Profile, || f.call::<_, ()>(config))?;
+    flush(lua)
+}
+
+/// Forget every defined profile, e.g. before the config is loaded again. The selected profile is kept
+pub fn clear_profiles(lua: &Lua) {
+    if let Some(profiles) = lua.remove_app_data::<Profiles>() {
+        for (_, f) in profiles.0 {
+            let _ = lua.remove_registry_value(f);
+        }
+    }
+    lua.expire_registry_values();
+}
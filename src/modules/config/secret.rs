@@ -0,0 +1,472 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display};
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use mlua::{
+    AnyUserData, Error as LuaError, FromLua, IntoLua, Lua, MetaMethod, Table, UserData, UserDataFields, UserDataMethods, Value,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::path::ConfigPath;
+use super::value::{add_methods, assign, config_error, is_strict, method_definitions, schema_object, warn, ConfigValue, LuaConfig};
+use crate::check::Diagnostics;
+use crate::discovery;
+use crate::lua::{LuaFmt, LuaStructFormat};
+use crate::types::{Class, LuaClass};
+
+/// Printed instead of the value of a secret
+pub const REDACTED: &str = "<redacted>";
+
+/// Environment variable holding the passphrase of the encrypted secret store
+pub const SECRETS_KEY_VAR: &str = "SLUA_SECRETS_KEY";
+
+/// Where the value of a [`Secret`] is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// An environment variable
+    Env(String),
+    /// A file, without its trailing newline
+    File(PathBuf),
+    /// An entry of an encrypted [`SecretStore`]
+    Store { path: PathBuf, name: String },
+}
+
+impl Display for SecretSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretSource::Env(name) => write!(f, "env:{name}"),
+            SecretSource::File(path) => write!(f, "file:{}", path.display()),
+            SecretSource::Store { path, name } => write!(f, "store:{}#{name}", path.display()),
+        }
+    }
+}
+
+/// A config value that is never printed.
+///
+/// `Debug`, `Display`, [`LuaFmt`], `Serialize` and `tostring` in lua all write [`REDACTED`];
+/// the value is only available through [`Secret::expose`], or `secret:reveal()` in lua.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret {
+    source: SecretSource,
+    value: String,
+}
+
+impl Secret {
+    /// Read the value of `source`
+    pub fn read(source: SecretSource) -> mlua::Result<Self> {
+        let value = match &source {
+            SecretSource::Env(name) => std::env::var(name)
+                .map_err(|_| LuaError::RuntimeError(format!("environment variable {name} is not set")))?,
+            SecretSource::File(path) => std::fs::read_to_string(path)
+                .map_err(|err| LuaError::RuntimeError(format!("could not read {}: {err}", path.display())))?
+                .trim_end_matches(['\n', '\r'])
+                .to_string(),
+            SecretSource::Store { path, name } => SecretStore::open(path)?
+                .get(name)?
+                .ok_or_else(|| LuaError::RuntimeError(format!("{} has no secret named {name}", path.display())))?,
+        };
+        Ok(Self { source, value })
+    }
+
+    /// The secret value, only use it where it is needed and never print it
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    /// Where the value was read from
+    pub fn source(&self) -> &SecretSource {
+        &self.source
+    }
+
+    /// Create a secret from the arguments of the lua `secret` function, see [`Secret::lua_class`].
+    ///
+    /// A source that can not be read is a diagnostic in check mode, an error in strict mode and a
+    /// warning otherwise; the secret is empty in all but strict mode.
+    pub fn from_lua_args<'lua>(lua: &'lua Lua, args: Value<'lua>) -> mlua::Result<Self> {
+        let source = match args {
+            Value::String(name) => SecretSource::Env(name.to_str()?.to_string()),
+            Value::Table(table) => {
+                let path = |key: &str| -> mlua::Result<Option<PathBuf>> {
+                    let path = table.get::<_, Option<String>>(key)?;
+                    path.map(|path| Ok(ConfigPath::from_lua_value(path.into_lua(lua)?, lua)?.resolved().to_path_buf()))
+                        .transpose()
+                };
+                match (table.get::<_, Option<String>>("env")?, path("file")?, table.get::<_, Option<String>>("store")?) {
+                    (Some(name), None, None) => SecretSource::Env(name),
+                    (None, Some(path), None) => SecretSource::File(path),
+                    (None, None, Some(name)) => {
+                        let Some(path) = path("path")?.or_else(|| discovery::secrets_file(lua)) else {
+                            return Err(config_error(lua, "no config directory for the secret store, set `path`"));
+                        };
+                        SecretSource::Store { path, name }
+                    }
+                    _ => return Err(config_error(lua, "secret needs exactly one of `env`, `file` or `store`")),
+                }
+            }
+            other => return Err(config_error(lua, format!("secret needs a string or table; was {}", other.type_name()))),
+        };
+
+        match Secret::read(source.clone()) {
+            Ok(secret) => Ok(secret),
+            Err(err) => {
+                let message = format!("could not read secret {source}: {}", err.to_string().trim_start_matches("runtime error: "));
+                if !Diagnostics::report(lua, &message) {
+                    if is_strict(lua) {
+                        return Err(config_error(lua, message));
+                    }
+                    warn(lua, format!("{message}, using an empty secret"));
+                }
+                Ok(Self { source, value: String::new() })
+            }
+        }
+    }
+}
+
+/// An empty secret, read from an environment variable without a name
+impl Default for Secret {
+    fn default() -> Self {
+        Self { source: SecretSource::Env(String::new()), value: String::new() }
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Secret").field("source", &self.source).field("value", &REDACTED).finish()
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'a> LuaFmt<'a> for Secret {
+    fn lua_fmt(&self, _: bool, _: usize) -> String {
+        REDACTED.to_string()
+    }
+}
+
+impl ConfigValue for Secret {
+    fn lua_type() -> String {
+        "Secret".into()
+    }
+
+    fn json_schema(_: &Lua) -> serde_json::Value {
+        json!({ "type": "string", "const": REDACTED })
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::UserData(data) if data.is::<Secret>())
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.clone().into_lua(lua)
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        match value {
+            Value::UserData(data) if data.is::<Secret>() => Ok(data.borrow::<Secret>()?.clone()),
+            other => Err(config_error(lua, format!("secrets must be created with secret(...); was {}", other.type_name()))),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for Secret {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        Self::from_lua_value(value, lua)
+    }
+}
+
+impl LuaClass for Secret {
+    fn lua_class() -> Class {
+        Class::new("Secret", "A value that is printed as `<redacted>`, created with `secret(...)`")
+            .field("source", "string", "Where the value was read from, e.g. `env:GITHUB_TOKEN`")
+            .field("reveal", "fun(self: Secret): string", "The secret value, never print it")
+    }
+}
+
+impl UserData for Secret {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("source", |_, this| Ok(this.source.to_string()));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("reveal", |_, this, ()| Ok(this.value.clone()));
+        methods.add_meta_method(MetaMethod::ToString, |_, _, _: Option<bool>| Ok(REDACTED));
+        methods.add_meta_method(MetaMethod::Eq, |_, this, other: AnyUserData| {
+            Ok(other.borrow::<Secret>().is_ok_and(|other| *this == *other))
+        });
+    }
+}
+
+/// Names that can not be used for secrets because they are methods of `config.secrets`
+const RESERVED: [&str; 4] = ["replace", "resolved", "on_change", "origin"];
+
+/// Secrets used by plugins, e.g. api tokens, set with `config.secrets.<name> = secret(...)`.
+///
+/// Secrets are never written to a dump or saved config, so they are not read back either.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Secrets(BTreeMap<String, Secret>);
+
+/// Always empty, the dumped values are [`REDACTED`]
+impl<'de> Deserialize<'de> for Secrets {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde::de::IgnoredAny::deserialize(deserializer)?;
+        Ok(Self::default())
+    }
+}
+
+impl Secrets {
+    pub fn get(&self, name: &str) -> Option<&Secret> {
+        self.0.get(name)
+    }
+
+    /// Set a secret from rust, without any checks
+    pub fn set<N: Into<String>>(&mut self, name: N, secret: Secret) {
+        self.0.insert(name.into(), secret);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add every secret of `other`, replacing secrets with the same name
+    pub fn extend(&mut self, other: &Secrets) {
+        self.0.extend(other.0.clone());
+    }
+}
+
+impl<'a> LuaFmt<'a> for Secrets {
+    fn lua_fmt(&self, pretty: bool, indent: usize) -> String {
+        self.0
+            .iter()
+            .fold(LuaStructFormat::new(pretty, indent), |format, (name, secret)| format.field(name, secret))
+            .to_string()
+    }
+}
+
+impl LuaConfig for Secrets {
+    const NAME: &'static str = "Secrets";
+    const KEY: &'static str = "secrets";
+    const FIELDS: &'static [&'static str] = &[];
+
+    fn keys(_: &Lua) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn json_schema(lua: &Lua) -> serde_json::Value {
+        let mut schema = schema_object("Secrets used by plugins, always redacted", Vec::new());
+        schema["additionalProperties"] = Secret::json_schema(lua);
+        schema
+    }
+
+    fn field_names(&self, _: &Lua) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+
+    fn is_field(_: &str) -> bool {
+        true
+    }
+
+    fn get_field<'lua>(&self, lua: &'lua Lua, key: &str) -> mlua::Result<Option<Value<'lua>>> {
+        match self.0.get(key) {
+            Some(secret) => secret.to_lua(lua).map(Some),
+            None => Ok(Some(Value::Nil)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, lua: &'lua Lua, name: &str, value: Value<'lua>) -> mlua::Result<bool> {
+        if RESERVED.contains(&name) {
+            return Ok(false);
+        }
+
+        let mut slot = self.0.get(name).cloned().unwrap_or_default();
+        assign(lua, &mut slot, Self::KEY, name, value)?;
+        self.0.insert(name.to_string(), slot);
+        Ok(true)
+    }
+
+    /// The secrets themselves, still redacted
+    fn resolved<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let table = lua.create_table()?;
+        for (name, secret) in self.0.iter() {
+            table.set(name.as_str(), secret.to_lua(lua)?)?;
+        }
+        Ok(table)
+    }
+
+    fn from_table<'lua>(table: &Table<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        let mut secrets = Secrets::default();
+        secrets.merge(table, lua)?;
+        Ok(secrets)
+    }
+
+    fn merge<'lua>(&mut self, table: &Table<'lua>, lua: &'lua Lua) -> mlua::Result<()> {
+        for pair in table.clone().pairs::<String, Value>() {
+            let (name, value) = pair?;
+            self.set_field(lua, &name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'lua> FromLua<'lua> for Secrets {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        super::value::from_lua(value, lua)
+    }
+}
+
+impl LuaClass for Secrets {
+    fn lua_class() -> Class {
+        method_definitions::<Self>(
+            Class::new(Self::NAME, "Secrets used by plugins, printed as `<redacted>`")
+                .field("[string]", "Secret", "Secrets created with `secret(...)`"),
+        )
+    }
+}
+
+impl UserData for Secrets {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(_: &mut F) {}
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        add_methods(methods);
+    }
+}
+
+/// The encrypted store file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreFile {
+    /// Salt of the key derived from the passphrase
+    salt: String,
+    /// Nonce and ciphertext of every secret, base64 encoded
+    secrets: BTreeMap<String, String>,
+}
+
+/// A json file of secrets encrypted with a key derived from [`SECRETS_KEY_VAR`].
+///
+/// Every secret is encrypted with ChaCha20-Poly1305, its name is authenticated along with it.
+pub struct SecretStore {
+    path: PathBuf,
+    key: Key,
+    file: StoreFile,
+}
+
+impl SecretStore {
+    /// Open the store at `path`, or a new empty store when the file does not exist
+    pub fn open<P: AsRef<Path>>(path: P) -> mlua::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let passphrase = std::env::var(SECRETS_KEY_VAR)
+            .map_err(|_| LuaError::RuntimeError(format!("set {SECRETS_KEY_VAR} to the passphrase of {}", path.display())))?;
+
+        let file = match path.is_file() {
+            true => serde_json::from_str(&std::fs::read_to_string(&path).map_err(LuaError::external)?)
+                .map_err(LuaError::external)?,
+            false => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                StoreFile { salt: BASE64.encode(salt), secrets: BTreeMap::new() }
+            }
+        };
+
+        let salt = BASE64.decode(&file.salt).map_err(LuaError::external)?;
+        let mut key = Key::default();
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|err| LuaError::RuntimeError(err.to_string()))?;
+        Ok(Self { path, key, file })
+    }
+
+    /// Names of every secret, sorted
+    pub fn names(&self) -> Vec<String> {
+        self.file.secrets.keys().cloned().collect()
+    }
+
+    /// Decrypt the secret named `name`
+    pub fn get(&self, name: &str) -> mlua::Result<Option<String>> {
+        let Some(encoded) = self.file.secrets.get(name) else { return Ok(None) };
+        let bytes = BASE64.decode(encoded).map_err(LuaError::external)?;
+        let invalid = || {
+            LuaError::RuntimeError(format!(
+                "could not decrypt {name} in {}, is {SECRETS_KEY_VAR} the right passphrase?",
+                self.path.display()
+            ))
+        };
+        if bytes.len() < 12 {
+            return Err(invalid());
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(12);
+        let value = ChaCha20Poly1305::new(&self.key)
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: name.as_bytes() })
+            .map_err(|_| invalid())?;
+        String::from_utf8(value).map(Some).map_err(LuaError::external)
+    }
+
+    /// Encrypt `value` as the secret named `name`, call [`SecretStore::save`] to write it
+    pub fn set(&mut self, name: &str, value: &str) -> mlua::Result<()> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, Payload { msg: value.as_bytes(), aad: name.as_bytes() })
+            .map_err(|err| LuaError::RuntimeError(err.to_string()))?;
+        let bytes = nonce.iter().copied().chain(ciphertext).collect::<Vec<_>>();
+        self.file.secrets.insert(name.to_string(), BASE64.encode(bytes));
+        Ok(())
+    }
+
+    /// Write the store, creating its directory
+    pub fn save(&self) -> mlua::Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(LuaError::external)?;
+        }
+        let contents = serde_json::to_string_pretty(&self.file).map_err(LuaError::external)?;
+        std::fs::write(&self.path, contents).map_err(LuaError::external)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A single test, the passphrase is read from the environment of the whole process
+    #[test]
+    fn store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("slua-secrets-{}", std::process::id()));
+        let path = dir.join("secrets.json");
+
+        std::env::set_var(SECRETS_KEY_VAR, "correct horse");
+        let mut store = SecretStore::open(&path).unwrap();
+        store.set("token", "hunter2").unwrap();
+        store.set("other", "").unwrap();
+        store.save().unwrap();
+
+        let store = SecretStore::open(&path).unwrap();
+        assert_eq!(store.names(), ["other", "token"]);
+        assert_eq!(store.get("token").unwrap().as_deref(), Some("hunter2"));
+        assert_eq!(store.get("other").unwrap().as_deref(), Some(""));
+        assert_eq!(store.get("missing").unwrap(), None);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("hunter2"));
+
+        std::env::set_var(SECRETS_KEY_VAR, "wrong passphrase");
+        let store = SecretStore::open(&path).unwrap();
+        let err = store.get("token").unwrap_err().to_string();
+        assert!(err.contains("could not decrypt token"), "{err}");
+
+        std::env::remove_var(SECRETS_KEY_VAR);
+        assert!(SecretStore::open(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+/// Immutable plain-data copy of a config, every value by its full key, e.g. `paths.build`.
+///
+/// Values are the same as in the json dump.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Snapshot(BTreeMap<String, JsonValue>);
+
+impl Snapshot {
+    /// Snapshot of anything that serializes to a json object, e.g. a [`Config`](super::Config)
+    pub fn new<T: Serialize>(value: &T) -> serde_json::Result<Self> {
+        let mut values = BTreeMap::new();
+        flatten(&mut values, "", serde_json::to_value(value)?);
+        Ok(Self(values))
+    }
+
+    /// Value of a key, `None` for sections and unknown keys
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.0.get(key)
+    }
+
+    /// Every key and value, sorted by key
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &JsonValue)> {
+        self.0.iter()
+    }
+}
+
+fn flatten(values: &mut BTreeMap<String, JsonValue>, prefix: &str, value: JsonValue) {
+    match value {
+        JsonValue::Object(object) => {
+            for (key, value) in object {
+                let key = if prefix.is_empty() { key } else { format!("{prefix}.{key}") };
+                flatten(values, &key, value);
+            }
+        }
+        value => {
+            values.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+/// A key whose value differs between two snapshots
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    pub key: String,
+    /// `None` when the key was added
+    pub old: Option<JsonValue>,
+    /// `None` when the key was removed
+    pub new: Option<JsonValue>,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "~ {}: {old} -> {new}", self.key),
+            (None, Some(new)) => write!(f, "+ {} = {new}", self.key),
+            (Some(old), None) => write!(f, "- {} = {old}", self.key),
+            (None, None) => write!(f, "  {}", self.key),
+        }
+    }
+}
+
+/// Compare two snapshots key by key, sorted by key
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Difference> {
+    let mut keys = old.0.keys().chain(new.0.keys()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let (old, new) = (old.get(key), new.get(key));
+            (old != new).then(|| Difference { key: key.clone(), old: old.cloned(), new: new.cloned() })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn snapshot(value: JsonValue) -> Snapshot {
+        Snapshot::new(&value).unwrap()
+    }
+
+    #[test]
+    fn snapshot_flattens_sections() {
+        let snapshot = snapshot(json!({ "paths": { "build": "out" }, "features": { "x": 1 } }));
+        assert_eq!(snapshot.get("paths.build"), Some(&json!("out")));
+        assert_eq!(snapshot.get("features.x"), Some(&json!(1)));
+        assert_eq!(snapshot.get("paths"), None);
+    }
+
+    #[test]
+    fn diff_reports_changed_added_and_removed_keys() {
+        let old = snapshot(json!({ "paths": { "build": "a", "download": "d" }, "features": { "old": true } }));
+        let new = snapshot(json!({ "paths": { "build": "b", "download": "d" }, "features": { "new": 2 } }));
+
+        let differences = diff(&old, &new);
+        assert_eq!(
+            differences,
+            vec![
+                Difference { key: "features.new".into(), old: None, new: Some(json!(2)) },
+                Difference { key: "features.old".into(), old: Some(json!(true)), new: None },
+                Difference { key: "paths.build".into(), old: Some(json!("a")), new: Some(json!("b")) },
+            ]
+        );
+        assert_eq!(
+            differences.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["+ features.new = 2", "- features.old = true", "~ paths.build: \"a\" -> \"b\""]
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,624 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use mlua::{AnyUserData, FromLua, Function, IntoLua, Lua, LuaSerdeExt, MetaMethod, SerializeOptions, Table, UserData, UserDataMethods, Value};
+use serde_json::json;
+
+use super::layer::{history, record};
+use super::observe::{flush, notify, observed, on_change};
+use crate::check::Diagnostics;
+use crate::lua::{caller_location, LuaFmt};
+use crate::types::Class;
+
+/// A type that can be used as a field of a [`LuaConfig`] struct
+pub trait ConfigValue: Sized {
+    /// Lua type used in type definitions and error messages
+    fn lua_type() -> String;
+
+    /// JSON Schema of the value in json and toml dumps
+    fn json_schema(lua: &Lua) -> serde_json::Value;
+
+    /// Whether the value has the expected lua type, without relying on lua's coercions
+    fn is_lua_type(value: &Value) -> bool;
+
+    /// Value returned to lua when the field is read
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>>;
+
+    /// Whether assigning a table merges into the current value instead of replacing it
+    fn is_section() -> bool {
+        false
+    }
+
+    /// Keys of the fields inside of this value, relative to it. Empty for anything but sections
+    fn keys(_: &Lua) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Copy of the current value given to change observers, sections are not shared with the original
+    fn snapshot<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.to_lua(lua)
+    }
+
+    /// Value returned by `resolved()`, e.g. a path after expansion
+    fn resolved_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.to_lua(lua)
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self>;
+
+    /// Update the field from a lua value
+    fn assign<'lua>(&mut self, value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<()> {
+        *self = Self::from_lua_value(value, lua)?;
+        Ok(())
+    }
+
+    /// Replace the value when the section that holds it is replaced
+    fn replace_with(&mut self, new: Self) {
+        *self = new;
+    }
+}
+
+impl ConfigValue for bool {
+    fn lua_type() -> String {
+        "boolean".into()
+    }
+
+    fn json_schema(_: &Lua) -> serde_json::Value {
+        json!({ "type": "boolean" })
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::Boolean(_))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.into_lua(lua)
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        bool::from_lua(value, lua)
+    }
+}
+
+impl ConfigValue for String {
+    fn lua_type() -> String {
+        "string".into()
+    }
+
+    fn json_schema(_: &Lua) -> serde_json::Value {
+        json!({ "type": "string" })
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::String(_))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.as_str().into_lua(lua)
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        String::from_lua(value, lua)
+    }
+}
+
+/// Paths are exposed to lua as strings
+impl ConfigValue for PathBuf {
+    fn lua_type() -> String {
+        "string".into()
+    }
+
+    fn json_schema(_: &Lua) -> serde_json::Value {
+        json!({ "type": "string" })
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::String(_))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.display().to_string().into_lua(lua)
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        Ok(PathBuf::from(String::from_lua(value, lua)?))
+    }
+}
+
+/// Nested config sections are shared with lua, so changes made through the
+/// userdata are visible from rust and the other way around
+impl<T: LuaConfig + UserData> ConfigValue for Arc<Mutex<T>> {
+    fn lua_type() -> String {
+        T::NAME.into()
+    }
+
+    fn json_schema(lua: &Lua) -> serde_json::Value {
+        T::json_schema(lua)
+    }
+
+    fn is_lua_type(value: &Value) -> bool {
+        matches!(value, Value::Table(_) | Value::UserData(_))
+    }
+
+    fn to_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.clone().into_lua(lua)
+    }
+
+    fn is_section() -> bool {
+        true
+    }
+
+    fn keys(lua: &Lua) -> Vec<String> {
+        T::keys(lua)
+    }
+
+    fn snapshot<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        let copy = self.lock().unwrap().clone();
+        lua.create_userdata(copy).map(Value::UserData)
+    }
+
+    fn resolved_lua<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
+        self.lock().unwrap().resolved(lua).map(Value::Table)
+    }
+
+    fn from_lua_value<'lua>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<Self> {
+        Ok(Arc::new(Mutex::new(from_lua(value, lua)?)))
+    }
+
+    /// Tables are merged into the current values, other sections replace them.
+    /// The contents are updated in place so every existing reference sees the new values
+    fn assign<'lua>(&mut self, value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<()> {
+        match value {
+            Value::Table(table) => self.lock().unwrap().merge(&table, lua),
+            value => {
+                let new = from_lua::<T>(value, lua)?;
+                self.lock().unwrap().replace_with(new);
+                Ok(())
+            }
+        }
+    }
+
+    /// The contents are replaced in place, `new` may be this same section
+    fn replace_with(&mut self, new: Self) {
+        let new = new.lock().unwrap().clone();
+        self.lock().unwrap().replace_with(new);
+    }
+}
+
+/// A struct exposed to lua as a section of the config.
+///
+/// Implement with `#[derive(LuaConfig)]`.
+pub trait LuaConfig: Clone + 'static {
+    /// Lua class name
+    const NAME: &'static str;
+    /// Key of the section inside of `config`, empty for the root
+    const KEY: &'static str;
+    /// Lua names of all exposed fields in declaration order
+    const FIELDS: &'static [&'static str];
+
+    /// Every key inside of the section, including those of nested sections, e.g. `paths.build`
+    fn keys(lua: &Lua) -> Vec<String>;
+
+    /// JSON Schema of the section, see [`schema_object`]
+    fn json_schema(lua: &Lua) -> serde_json::Value;
+
+    /// Lua names of the fields of this value, used by `pairs`
+    fn field_names(&self, _: &Lua) -> Vec<String> {
+        Self::FIELDS.iter().map(|field| field.to_string()).collect()
+    }
+
+    /// Whether a table key is a field, unknown keys are reported
+    fn is_field(key: &str) -> bool {
+        Self::FIELDS.contains(&key)
+    }
+
+    /// Read a field by its lua name, `None` if there is no such field
+    fn get_field<'lua>(&self, lua: &'lua Lua, key: &str) -> mlua::Result<Option<Value<'lua>>>;
+
+    /// Write a field by its lua name, `false` if there is no such field
+    fn set_field<'lua>(&mut self, lua: &'lua Lua, key: &str, value: Value<'lua>) -> mlua::Result<bool>;
+
+    /// Table of every field after resolving it, e.g. paths are expanded
+    fn resolved<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>>;
+
+    /// Create from a table, fields missing from the table use their default
+    fn from_table<'lua>(table: &Table<'lua>, lua: &'lua Lua) -> mlua::Result<Self>;
+
+    /// Replace every value with those of `new`. Nested sections are replaced inside of their
+    /// mutexes, so references to them that lua or rust already hold see the new values
+    fn replace_with(&mut self, new: Self) {
+        *self = new;
+    }
+
+    /// Update only the fields that are set in the table, nested sections are merged as well
+    fn merge<'lua>(&mut self, table: &Table<'lua>, lua: &'lua Lua) -> mlua::Result<()> {
+        check_keys::<Self>(lua, table)?;
+        for field in Self::FIELDS {
+            match table.get::<_, Value>(*field)? {
+                Value::Nil => {}
+                value => {
+                    self.set_field(lua, field, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Json value used for schemas, so `#[derive(LuaConfig)]` does not require a `serde_json` dependency
+pub type JsonValue = serde_json::Value;
+
+/// JSON Schema of a section with the `(name, schema, doc)` of each field.
+///
+/// Only the listed fields are allowed, none of them are required as missing fields use their default.
+pub fn schema_object(doc: &str, fields: Vec<(&str, serde_json::Value, &str)>) -> serde_json::Value {
+    let properties = fields
+        .into_iter()
+        .map(|(name, mut schema, doc)| {
+            if !doc.is_empty() {
+                schema["description"] = doc.into();
+            }
+            (name.to_string(), schema)
+        })
+        .collect::<serde_json::Map<_, _>>();
+
+    let mut schema = json!({ "type": "object", "properties": properties, "additionalProperties": false });
+    if !doc.is_empty() {
+        schema["description"] = doc.into();
+    }
+    schema
+}
+
+/// Full key of a field, e.g. `paths.build`
+pub fn key(section: &str, field: &str) -> String {
+    if section.is_empty() {
+        field.to_string()
+    } else {
+        format!("{section}.{field}")
+    }
+}
+
+/// Marker stored in the lua app data while strict mode is enabled
+struct Strict;
+
+/// Enable or disable strict mode.
+///
+/// In strict mode unknown keys and values of the wrong type in config tables
+/// raise an error instead of printing a warning and being ignored or replaced by their default.
+pub fn set_strict(lua: &Lua, strict: bool) {
+    if strict {
+        lua.set_app_data(Strict);
+    } else {
+        lua.remove_app_data::<Strict>();
+    }
+}
+
+/// Whether strict mode is enabled
+pub fn is_strict(lua: &Lua) -> bool {
+    lua.app_data_ref::<Strict>().is_some()
+}
+
+/// Marker stored in the lua app data once the config is frozen
+struct Frozen;
+
+/// Freeze or unfreeze the config.
+///
+/// While frozen every write from lua raises an error, see [`Config::freeze`](super::Config::freeze).
+pub fn set_frozen(lua: &Lua, frozen: bool) {
+    if frozen {
+        lua.set_app_data(Frozen);
+    } else {
+        lua.remove_app_data::<Frozen>();
+    }
+}
+
+/// Whether the config is frozen
+pub fn is_frozen(lua: &Lua) -> bool {
+    lua.app_data_ref::<Frozen>().is_some()
+}
+
+/// Run `f` with config writes allowed, even if the config is frozen
+pub fn thawed<R>(lua: &Lua, f: impl FnOnce() -> R) -> R {
+    let frozen = lua.remove_app_data::<Frozen>();
+    let result = f();
+    if let Some(frozen) = frozen {
+        lua.set_app_data(frozen);
+    }
+    result
+}
+
+/// Error raised for an invalid config value, prefixed with the location of the lua code
+pub fn config_error<S: AsRef<str>>(lua: &Lua, message: S) -> mlua::Error {
+    match caller_location(lua) {
+        Some(location) => mlua::Error::RuntimeError(format!("{location}: {}", message.as_ref())),
+        None => mlua::Error::RuntimeError(message.as_ref().to_string()),
+    }
+}
+
+/// Print a problem with the config to stderr, prefixed with the location of the lua code.
+///
+/// Used outside of check and strict mode for values that are ignored, so they are not lost silently.
+pub fn warn<S: AsRef<str>>(lua: &Lua, message: S) {
+    match caller_location(lua) {
+        Some(location) => eprintln!("warning: {location}: {}", message.as_ref()),
+        None => eprintln!("warning: {}", message.as_ref()),
+    }
+}
+
+fn type_mismatch<T: ConfigValue>(section: &str, field: &str, value: &Value) -> String {
+    format!(
+        "config.{} must be a {}; was {}",
+        key(section, field),
+        T::lua_type(),
+        value.type_name()
+    )
+}
+
+fn frozen(key: &str) -> String {
+    format!("config is frozen; cannot set config.{key}")
+}
+
+fn unknown_value(section: &str, field: &str) -> String {
+    format!("config.{} is not a config value that can be observed", key(section, field))
+}
+
+/// Whether `field` is a key of a value inside of `T`, and not a section
+pub fn is_value_key<T: LuaConfig>(lua: &Lua, field: &str) -> bool {
+    let keys = T::keys(lua);
+    keys.iter().any(|k| k == field)
+        && !keys.iter().any(|k| k.strip_prefix(field).is_some_and(|rest| rest.starts_with('.')))
+}
+
+fn unknown_key(section: &str, field: &str, fields: &[&str]) -> String {
+    format!(
+        "config.{} is not a valid key; expected one of: {}",
+        key(section, field),
+        fields.join(", ")
+    )
+}
+
+/// Get a field from a config section table, falling back to the default when
+/// the field is missing or can not be converted.
+///
+/// Values that are not of the expected lua type use the default as well. They are reported when
+/// check mode is enabled, raise an error in strict mode and print a warning otherwise.
+pub fn field_or<'lua, T: ConfigValue>(
+    lua: &'lua Lua,
+    table: &Table<'lua>,
+    section: &str,
+    field: &str,
+    default: impl FnOnce() -> T,
+) -> mlua::Result<T> {
+    match table.get::<_, Value>(field) {
+        Ok(Value::Nil) | Err(_) => Ok(default()),
+        Ok(value) => {
+            if !T::is_lua_type(&value) {
+                let message = type_mismatch::<T>(section, field, &value);
+                if !Diagnostics::report(lua, &message) {
+                    if is_strict(lua) {
+                        return Err(config_error(lua, message));
+                    }
+                    warn(lua, format!("{message}, using the default"));
+                }
+                return Ok(default());
+            }
+            Ok(T::from_lua_value(value, lua).unwrap_or_else(|_| default()))
+        }
+    }
+}
+
+/// Assign a lua value to a config field and record the write in the current [`Layer`](super::Layer).
+///
+/// Values that are not of the expected lua type leave the field unchanged: they are reported in
+/// check mode, raise an error in strict mode and print a warning otherwise.
+pub fn assign<'lua, T: ConfigValue>(
+    lua: &'lua Lua,
+    target: &mut T,
+    section: &str,
+    field: &str,
+    value: Value<'lua>,
+) -> mlua::Result<()> {
+    if is_frozen(lua) {
+        return Err(config_error(lua, frozen(&key(section, field))));
+    }
+    if !T::is_lua_type(&value) {
+        let message = type_mismatch::<T>(section, field, &value);
+        if !Diagnostics::report(lua, &message) {
+            if is_strict(lua) {
+                return Err(config_error(lua, message));
+            }
+            warn(lua, format!("{message}, ignoring it"));
+        }
+        return Ok(());
+    }
+
+    // Merged tables record and notify every field they set instead of the whole section
+    let merge = T::is_section() && matches!(value, Value::Table(_));
+    let key = key(section, field);
+    let observed = if merge { Vec::new() } else { observed(lua, &key) };
+    let old = match observed.is_empty() {
+        true => None,
+        false => Some(target.snapshot(lua)?),
+    };
+
+    target.assign(value, lua)?;
+    if !merge {
+        record(lua, key.clone());
+    }
+    if let Some(old) = old {
+        notify(lua, &key, &observed, old, target.snapshot(lua)?)?;
+    }
+    Ok(())
+}
+
+/// Check a config table for keys that are not fields of `T`.
+///
+/// Unknown keys are reported in check mode, raise an error in strict mode and otherwise print a warning.
+pub fn check_keys<'lua, T: LuaConfig>(lua: &'lua Lua, table: &Table<'lua>) -> mlua::Result<()> {
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (k, _) = pair?;
+        let name = match &k {
+            Value::String(name) => name.to_str()?.to_string(),
+            other => format!("[{}]", other.type_name()),
+        };
+        if T::is_field(&name) {
+            continue;
+        }
+
+        let message = unknown_key(T::KEY, &name, T::FIELDS);
+        if !Diagnostics::report(lua, &message) {
+            if is_strict(lua) {
+                return Err(config_error(lua, message));
+            }
+            warn(lua, format!("{message}, ignoring it"));
+        }
+    }
+    Ok(())
+}
+
+/// Type definitions for the methods added by [`add_methods`]
+pub fn method_definitions<T: LuaConfig>(class: Class) -> Class {
+    class
+        .field(
+            "replace",
+            format!("fun(self: {0}, values: {0})", T::NAME),
+            "Replace every value, fields missing from `values` are reset to their default",
+        )
+        .field(
+            "resolved",
+            format!("fun(self: {0}): {0}", T::NAME),
+            "Plain table of the final values, with defaults and expansions applied",
+        )
+        .field(
+            "on_change",
+            format!("fun(self: {0}, key: string, f: fun(old: any, new: any))", T::NAME),
+            "Call `f` whenever the value of `key` (relative to this section) changes",
+        )
+        .field(
+            "origin",
+            format!("fun(self: {0}, key: string): ConfigOrigin[]", T::NAME),
+            "Every write to `key` (relative to this section), oldest first",
+        )
+}
+
+/// Access a config section that is either stored directly in the userdata or
+/// shared through an `Arc<Mutex<T>>`
+pub fn with_userdata<T: LuaConfig, R>(data: &AnyUserData, f: impl FnOnce(&T) -> R) -> mlua::Result<R> {
+    match data.borrow::<T>() {
+        Ok(this) => Ok(f(&this)),
+        Err(_) => Ok(f(&data.borrow::<Arc<Mutex<T>>>()?.lock().unwrap())),
+    }
+}
+
+/// Mutable version of [`with_userdata`]
+pub fn with_userdata_mut<T: LuaConfig, R>(data: &AnyUserData, f: impl FnOnce(&mut T) -> R) -> mlua::Result<R> {
+    match data.borrow_mut::<T>() {
+        Ok(mut this) => Ok(f(&mut this)),
+        Err(_) => Ok(f(&mut data.borrow::<Arc<Mutex<T>>>()?.lock().unwrap())),
+    }
+}
+
+/// Convert a table or userdata into a config section
+pub fn from_lua<'lua, T: LuaConfig>(value: Value<'lua>, lua: &'lua Lua) -> mlua::Result<T> {
+    match value {
+        Value::Table(table) => {
+            check_keys::<T>(lua, &table)?;
+            T::from_table(&table, lua)
+        }
+        Value::UserData(data) => with_userdata(&data, T::clone),
+        _ => {
+            let name = match T::KEY {
+                "" => "config".to_string(),
+                section => format!("config.{section}"),
+            };
+            Err(config_error(lua, format!("{name} must be a table or {}; was {}", T::NAME, value.type_name())))
+        }
+    }
+}
+
+/// Add `replace`, `resolved`, `origin`, `on_change`, `__tostring`, `__index` and `__newindex` for
+/// keys that are not a field, and `__pairs` to a config section
+pub fn add_methods<'lua, T: LuaConfig + LuaFmt<'lua> + UserData, M: UserDataMethods<'lua, T>>(methods: &mut M) {
+    // Converted before borrowing, `value` may be this same section
+    methods.add_function("replace", |lua, (this, value): (AnyUserData, Value)| {
+        if is_frozen(lua) {
+            return Err(config_error(lua, frozen(T::KEY)));
+        }
+        let new = from_lua::<T>(value, lua)?;
+        let observed = observed(lua, T::KEY);
+        let old = with_userdata_mut(&this, |this: &mut T| {
+            let old = (!observed.is_empty()).then(|| this.clone());
+            this.replace_with(new);
+            old
+        })?;
+        record(lua, T::KEY.to_string());
+        if let Some(old) = old {
+            let new = with_userdata(&this, T::clone)?;
+            notify(lua, T::KEY, &observed, Value::UserData(lua.create_userdata(old)?), Value::UserData(lua.create_userdata(new)?))?;
+        }
+        flush(lua)
+    });
+
+    methods.add_function("on_change", |lua, (_, field, f): (AnyUserData, String, Function)| {
+        if !is_value_key::<T>(lua, &field) {
+            return Err(config_error(lua, unknown_value(T::KEY, &field)));
+        }
+        on_change(lua, &key(T::KEY, &field), f, true)
+    });
+
+    methods.add_method("resolved", |lua, this, ()| this.resolved(lua));
+
+    methods.add_function("origin", |lua, (_, field): (AnyUserData, String)| {
+        if !T::keys(lua).contains(&field) {
+            return Err(config_error(lua, unknown_key(T::KEY, &field, T::FIELDS)));
+        }
+        let options = SerializeOptions::new().serialize_none_to_null(false);
+        lua.to_value_with(&history(lua, &key(T::KEY, &field)), options)
+    });
+
+    methods.add_meta_method(MetaMethod::ToString, |_, this, pretty: Option<bool>| {
+        Ok(this.lua_fmt(pretty.unwrap_or(false), 0))
+    });
+
+    // Only called for keys that are not a field or method, which are an error unless `T` has dynamic fields
+    methods.add_meta_function(MetaMethod::Index, |lua, (this, key): (AnyUserData, String)| {
+        match with_userdata(&this, |this: &T| this.get_field(lua, &key))?? {
+            Some(value) => Ok(value),
+            None => Err(config_error(lua, unknown_key(T::KEY, &key, T::FIELDS))),
+        }
+    });
+
+    methods.add_meta_function(MetaMethod::NewIndex, |lua, (this, key, value): (AnyUserData, Value, Value)| {
+        let name = match &key {
+            Value::String(name) => name.to_str()?.to_string(),
+            other => format!("[{}]", other.type_name()),
+        };
+        if with_userdata_mut(&this, |this: &mut T| this.set_field(lua, &name, value))?? {
+            return flush(lua);
+        }
+
+        let message = unknown_key(T::KEY, &name, T::FIELDS);
+        if Diagnostics::report(lua, &message) {
+            return Ok(());
+        }
+        Err(config_error(lua, message))
+    });
+
+    methods.add_meta_function(MetaMethod::Pairs, |lua, this: AnyUserData| {
+        let next = lua.create_function(|lua, (this, key): (AnyUserData, Option<String>)| {
+            let fields = with_userdata(&this, |this: &T| this.field_names(lua))?;
+            let index = match key {
+                None => 0,
+                Some(key) => fields.iter().position(|f| *f == key).map_or(fields.len(), |i| i + 1),
+            };
+            match fields.get(index) {
+                Some(field) => Ok((
+                    Value::String(lua.create_string(field)?),
+                    with_userdata(&this, |this: &T| this.get_field(lua, field))??.unwrap_or(Value::Nil),
+                )),
+                None => Ok((Value::Nil, Value::Nil)),
+            }
+        })?;
+        Ok((next, this, Value::Nil))
+    });
+}
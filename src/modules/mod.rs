@@ -3,9 +3,11 @@ mod prettify;
 pub mod config;
 
 use mlua::{Error as LuaError, Lua, Table};
-pub use plugin::Plugins;
+pub use plugin::{Plugin, Plugins};
 pub use prettify::{Prettify, pformat};
 
+use crate::types::{Class, MetaFile};
+
 pub trait Import {
     /// The name of the module
     fn module_name() -> &'static str;
@@ -13,8 +15,15 @@ pub trait Import {
     /// Extend an existing table with the modules contents
     fn extend(table: &Table, lua: &Lua) -> Result<(), LuaError>;
 
+    /// Lua language server definitions for the module.
+    ///
+    /// Defaults to an empty module class.
+    fn definitions() -> MetaFile {
+        MetaFile::new(Self::module_name()).class(Class::module(Self::module_name(), ""))
+    }
+
     /// Create the module and return it (Import)
-    fn import(lua: &Lua) -> Result<Table, LuaError> {
+    fn import(lua: &Lua) -> Result<Table<'_>, LuaError> {
         let table = lua.create_table()?;
         Self::extend(&table, lua)?;
         Ok(table)
@@ -2,8 +2,15 @@ use std::collections::HashMap;
 
 use mlua::{FromLua, Function, IntoLua, Lua, LuaSerdeExt, Table, Value};
 use mlua::prelude::{LuaError, LuaString};
+use serde::ser::SerializeStruct;
+use serde::Serialize;
 
 use super::Import;
+use crate::check::Diagnostics;
+use crate::types::{Class, Function as FunctionDef, LuaClass, MetaFile};
+
+/// Fields every plugin table must define as strings. All other fields are hooks.
+const REQUIRED_FIELDS: [&str; 4] = ["name", "version", "author", "description"];
 
 /// This object is only constructed from lua tables.
 /// it is used for parsing/validating tables for plugins along
@@ -20,6 +27,48 @@ pub struct Plugin<'lua> {
 }
 
 impl<'lua> Plugin<'lua> {
+    /// Validate a plugin table and return every problem found.
+    ///
+    /// Unlike `from_lua` this does not stop at the first invalid field.
+    pub fn problems(value: &Value<'lua>) -> Vec<String> {
+        let table = match value {
+            Value::Table(table) => table,
+            _ => return vec![format!("plugin must be a table; was {}", value.type_name())],
+        };
+
+        let mut problems = Vec::new();
+        for field in REQUIRED_FIELDS {
+            match table.get::<_, Value>(field) {
+                Ok(Value::String(_)) => {}
+                Ok(Value::Nil) => problems.push(format!("missing required field `{field}`")),
+                Ok(value) => problems.push(format!("`{field}` must be a string; was {}", value.type_name())),
+                Err(err) => problems.push(err.to_string()),
+            }
+        }
+
+        for pair in table.clone().pairs::<Value, Value>() {
+            match pair {
+                Ok((Value::String(key), value)) => {
+                    let key = key.to_str().unwrap_or("?");
+                    if !REQUIRED_FIELDS.contains(&key) && !matches!(value, Value::Function(_)) {
+                        problems.push(format!("hook `{key}` must be a function; was {}", value.type_name()));
+                    }
+                }
+                Ok((key, _)) => problems.push(format!("unexpected {} key in plugin table", key.type_name())),
+                Err(err) => problems.push(err.to_string()),
+            }
+        }
+
+        problems
+    }
+
+    /// Names of all hooks the plugin defines, sorted
+    pub fn hook_names(&self) -> Vec<&str> {
+        let mut names = self.hooks.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
     pub fn info(&self, lua: &'lua Lua) -> Result<Table<'lua>, LuaError> {
         let info = lua.create_table()?;
         info.set("name", self.name.clone())?;
@@ -30,6 +79,30 @@ impl<'lua> Plugin<'lua> {
     }
 }
 
+impl<'lua> LuaClass for Plugin<'lua> {
+    fn lua_class() -> Class {
+        Class::new("Plugin", "A table of plugin information and event hooks")
+            .field("name", "string", "")
+            .field("version", "string", "")
+            .field("author", "string", "")
+            .field("description", "string", "")
+            .field("setup?", "fun(plugin: PluginInfo)", "Called when the plugin is added")
+    }
+}
+
+/// Hooks are serialized by name only
+impl<'lua> Serialize for Plugin<'lua> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut plugin = serializer.serialize_struct("Plugin", 5)?;
+        plugin.serialize_field("name", &self.name)?;
+        plugin.serialize_field("version", &self.version)?;
+        plugin.serialize_field("author", &self.author)?;
+        plugin.serialize_field("description", &self.description)?;
+        plugin.serialize_field("hooks", &self.hook_names())?;
+        plugin.end()
+    }
+}
+
 impl<'lua> IntoLua<'lua> for Plugin<'lua> {
     fn into_lua(self, lua: &'lua Lua) -> mlua::Result<Value<'lua>> {
         let value = lua.create_table()?;
@@ -69,14 +142,31 @@ impl<'lua> FromLua<'lua> for Plugin<'lua> {
 }
 
 fn new_plugin(lua: &Lua, data: Value) -> Result<(), LuaError> {
+    // In check mode every problem is reported and invalid plugins are skipped
+    // instead of raising on the first invalid field
+    let checking = Diagnostics::enabled(lua);
+    if checking {
+        let problems = Plugin::problems(&data);
+        if !problems.is_empty() {
+            let name = match &data {
+                Value::Table(table) => table.get::<_, Option<String>>("name").ok().flatten(),
+                _ => None,
+            };
+            for problem in problems {
+                Diagnostics::report(lua, format!("plugin {}: {problem}", name.as_deref().unwrap_or("<unnamed>")));
+            }
+            return Ok(());
+        }
+    }
+
     // Parse input to new plugin as a table mapping to `Plugin`
     //  This step is purely for validation purposes
     let plugin = Plugin::from_lua(data.clone(), lua)?;
 
-    // Call any hooks for setup
+    // Call any hooks for setup. Hooks may have side effects so they are skipped in check mode
     log::info!("[\x1b[36mLUA\x1b[39m] Adding plugin {}", plugin.name);
-    if let Some(setup) = plugin.hooks.get("setup") {
-        setup.call(plugin.info(lua)?)?;
+    if let Some(setup) = plugin.hooks.get("setup").filter(|_| !checking) {
+        setup.call::<_, ()>(plugin.info(lua)?)?;
     }
 
     let plugins = Plugins::module(lua)?.get::<_, Table>("plugins")?;
@@ -92,7 +182,12 @@ impl Plugins {
         lua.globals().get::<_, Table>("plugins")
     }
 
-    pub fn get_plugins(lua: &Lua) -> Result<Vec<Plugin>, LuaError> {
+    /// Remove every registered plugin
+    pub fn reset(lua: &Lua) -> Result<(), LuaError> {
+        Plugins::module(lua)?.set("plugins", lua.create_table()?)
+    }
+
+    pub fn get_plugins(lua: &Lua) -> Result<Vec<Plugin<'_>>, LuaError> {
         Vec::<Plugin>::from_lua(Plugins::module(lua)?.get("plugins")?, lua)
     }
 }
@@ -102,6 +197,23 @@ impl Import for Plugins {
         "plugins"
     }
 
+    fn definitions() -> MetaFile {
+        MetaFile::new(Self::module_name())
+            .class(
+                Class::new("PluginInfo", "The plugin information stripped of the event hooks")
+                    .field("name", "string", "")
+                    .field("version", "string", "")
+                    .field("author", "string", "")
+                    .field("description", "string", ""),
+            )
+            .class(Plugin::lua_class())
+            .class(
+                Class::module(Self::module_name(), "Module for adding plugins")
+                    .field("plugins", "Plugin[]", "All plugins that have been added")
+                    .function(FunctionDef::new("new_plugin", "Add a new plugin").param("plugin", "Plugin", "")),
+            )
+    }
+
     fn extend(table: &Table<'_>, lua: &Lua) -> Result<(), LuaError> {
         table.set("plugins", lua.create_table()?)?;
         table.set("new_plugin", lua.create_function(new_plugin)?)?;
@@ -61,6 +61,16 @@ impl Prettify {
         Ok(())
     }
 
+    /// Same as [`Prettify::pprint`] but writes to stderr
+    pub fn epprint(_: &Lua, args: Variadic<Value>) -> mlua::Result<()> {
+        let args = args
+            .iter()
+            .map(|v| pformat(v, 0))
+            .collect::<Result<Vec<String>, LuaError>>()?.join(" ");
+        eprintln!("{}", args);
+        Ok(())
+    }
+
     fn pstring(_: &Lua, (arg, indent): (Value, Option<Integer>)) -> Result<String, LuaError> {
         pformat(&arg, indent.unwrap_or(0) as usize)
     }
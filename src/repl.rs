@@ -0,0 +1,208 @@
+use std::path::PathBuf;
+
+use mlua::{Error as LuaError, Function, Lua, MultiValue, Value};
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+
+use crate::modules::pformat;
+
+/// Interactive read-evaluate-print-loop over an existing lua state.
+///
+/// Expressions are evaluated and their results printed with [`pformat`].
+/// Incomplete input (an open `function`, `do`, etc.) continues on the next line.
+pub struct Repl<'lua> {
+    lua: &'lua Lua,
+    editor: Editor<LuaHelper<'lua>, DefaultHistory>,
+    history: Option<PathBuf>,
+}
+
+impl<'lua> Repl<'lua> {
+    pub fn new(lua: &'lua Lua) -> Result<Self, LuaError> {
+        let mut editor = Editor::new().map_err(LuaError::external)?;
+        editor.set_helper(Some(LuaHelper::new(lua)?));
+        Ok(Self {
+            lua,
+            editor,
+            history: None,
+        })
+    }
+
+    /// Load history from the file and persist it back when the repl exits.
+    ///
+    /// The file and its parent directories are created if they do not exist.
+    pub fn with_history<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        let path = path.into();
+        if path.exists() {
+            if let Err(err) = self.editor.load_history(&path) {
+                log::warn!("Failed to load repl history from {}: {err}", path.display());
+            }
+        }
+        self.history = Some(path);
+        self
+    }
+
+    /// Run until end of input (`Ctrl-D`).
+    ///
+    /// `Ctrl-C` discards the current input and starts a new prompt.
+    pub fn run(mut self) -> Result<(), LuaError> {
+        'repl: loop {
+            let mut prompt = "> ";
+            let mut line = String::new();
+
+            loop {
+                match self.editor.readline(prompt) {
+                    Ok(input) => line.push_str(&input),
+                    Err(ReadlineError::Interrupted) => continue 'repl,
+                    Err(ReadlineError::Eof) => break 'repl,
+                    Err(err) => return Err(LuaError::external(err)),
+                }
+
+                match self.lua.load(&line).set_name("=stdin").eval::<MultiValue>() {
+                    Ok(values) => {
+                        self.editor.add_history_entry(line).map_err(LuaError::external)?;
+                        if !values.is_empty() {
+                            match values
+                                .iter()
+                                .map(|value| pformat(value, 0))
+                                .collect::<Result<Vec<_>, _>>()
+                            {
+                                Ok(values) => println!("{}", values.join("\t")),
+                                Err(err) => eprintln!("error: {}", err),
+                            }
+                        }
+                        break;
+                    }
+                    Err(LuaError::SyntaxError {
+                        incomplete_input: true,
+                        ..
+                    }) => {
+                        // continue reading input and append it to `line`
+                        line.push('\n');
+                        prompt = ">> ";
+                    }
+                    Err(err) => {
+                        self.editor.add_history_entry(line).map_err(LuaError::external)?;
+                        eprintln!("error: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.save_history()
+    }
+
+    fn save_history(&mut self) -> Result<(), LuaError> {
+        if let Some(path) = &self.history {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(LuaError::external)?;
+            }
+            self.editor.save_history(path).map_err(LuaError::external)?;
+        }
+        Ok(())
+    }
+}
+
+/// Completes identifiers by walking the lua globals.
+///
+/// `a.b.` resolves `a.b` with normal lua indexing and completes the keys of the
+/// result. Both tables and userdata implementing `__pairs` are supported.
+pub struct LuaHelper<'lua> {
+    lua: &'lua Lua,
+    index: Function<'lua>,
+    pairs: Function<'lua>,
+}
+
+impl<'lua> LuaHelper<'lua> {
+    pub fn new(lua: &'lua Lua) -> Result<Self, LuaError> {
+        Ok(Self {
+            lua,
+            index: lua
+                .load("local value, key = ...; return value[key]")
+                .set_name("=completion")
+                .into_function()?,
+            pairs: lua.globals().get("pairs")?,
+        })
+    }
+
+    /// All string keys of a table or userdata, using `__pairs` when it is defined
+    fn keys(&self, value: &Value<'lua>) -> Vec<String> {
+        let mut keys = Vec::new();
+        if !matches!(value, Value::Table(_) | Value::UserData(_)) {
+            return keys;
+        }
+
+        let Ok((next, state, mut key)) = self.pairs.call::<_, (Function, Value, Value)>(value.clone()) else {
+            return keys;
+        };
+        while let Ok((k, _)) = next.call::<_, (Value, Value)>((state.clone(), key)) {
+            if let Value::Nil = k {
+                break;
+            }
+            if let Value::String(name) = &k {
+                if let Ok(name) = name.to_str() {
+                    keys.push(name.to_string());
+                }
+            }
+            key = k;
+        }
+        keys
+    }
+
+    /// Resolve a `.` separated path starting from the globals
+    fn resolve(&self, path: &[&str]) -> Option<Value<'lua>> {
+        let mut value = Value::Table(self.lua.globals());
+        for segment in path {
+            value = self.index.call::<_, Value>((value, *segment)).ok()?;
+        }
+        Some(value)
+    }
+}
+
+impl<'lua> Completer for LuaHelper<'lua> {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | ':')))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+
+        // `a.b:c` completes `c` from the keys of `a.b` just like `a.b.c`
+        let (path, partial, offset) = match word.rfind(['.', ':']) {
+            Some(i) => (word[..i].split(['.', ':']).collect::<Vec<_>>(), &word[i + 1..], start + i + 1),
+            None => (Vec::new(), word, start),
+        };
+
+        let mut candidates = match self.resolve(&path) {
+            Some(value) => self
+                .keys(&value)
+                .into_iter()
+                .filter(|key| key.starts_with(partial))
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        candidates.sort();
+        candidates.dedup();
+
+        Ok((offset, candidates))
+    }
+}
+
+impl<'lua> Hinter for LuaHelper<'lua> {
+    type Hint = String;
+}
+
+impl<'lua> Highlighter for LuaHelper<'lua> {}
+
+impl<'lua> Validator for LuaHelper<'lua> {}
+
+impl<'lua> Helper for LuaHelper<'lua> {}
@@ -0,0 +1,159 @@
+use std::path::Path;
+
+use mlua::{Function, Lua, Table, Value, Variadic};
+
+use crate::discovery;
+use crate::lua as _lua;
+use crate::modules::config::{self, Config, Flag, FlagValue, Layer, Secret};
+use crate::modules::{Import, Plugins, Prettify, Require};
+use crate::types::{Class, Function as FunctionDef, MetaFile, TypeRegistry};
+
+/// Register the standard slua modules and globals.
+///
+/// - `plugins`: the [`Plugins`] module
+/// - `v`: the root namespace with `v.print` pretty printing
+/// - `config`: a default [`Config`], with the built-in feature flags declared
+/// - `secret`: creates a [`Secret`] from an environment variable, file or encrypted store
+/// - `types.provided`: helper functions added to the lua standard library
+///
+/// `package.path` should already point at the config directory so that
+/// `types.provided` can be resolved from there.
+pub fn load_std(lua: &Lua) -> Result<(), mlua::Error> {
+    lua.require::<Plugins>()?;
+    lua.import("v", _lua::module! { [lua]
+        "print" => Prettify::pprint,
+    }?)?;
+
+    lua.globals().set("config", Config::default())?;
+    config::declare(lua, Flag::new("show_docker_logs", FlagValue::Boolean(false), "show or hide docker command stdout responses"))?;
+    lua.globals().set("secret", lua.create_function(|lua, source: Value| Secret::from_lua_args(lua, source))?)?;
+
+    log::info!("[\x1b[31mRUST\x1b[39m] Loading provided.lua");
+    lua.load("require 'types.provided'").exec()
+}
+
+/// Load the config layers in order, later layers override earlier values:
+///
+/// 1. built-in defaults, set by [`load_std`]
+/// 2. [`SYSTEM_INIT`](discovery::SYSTEM_INIT) if it exists
+/// 3. the [`SAVED_FILE`](discovery::SAVED_FILE) written by `config:save()` if it exists
+/// 4. the user `init` module, required from the config directory
+/// 5. the [`PROJECT_FILE`](discovery::PROJECT_FILE) found by walking up from the current directory
+/// 6. the profile selected with `--profile` or `SLUA_PROFILE`, see [`config::apply_profile`]
+/// 7. `SLUA_<SECTION>__<KEY>` environment variables, then `--set` arguments, see [`config::apply_overrides`]
+///
+/// Every config write is recorded with its layer, see [`config::layer_of`]. Feature flags are
+/// validated once every layer is loaded, as plugins may declare them after they are set.
+pub fn load_config(lua: &Lua, init: &str) -> Result<(), mlua::Error> {
+    if let Some(system) = discovery::system_init() {
+        log::info!("[\x1b[31mRUST\x1b[39m] Loading {}", system.display());
+        config::with_layer(lua, Layer::System, || lua.load(system.as_path()).exec())?;
+    }
+
+    if let Some(saved) = discovery::saved_file(lua).filter(|file| file.is_file()) {
+        log::info!("[\x1b[31mRUST\x1b[39m] Loading {}", saved.display());
+        let dir = saved.parent().unwrap_or(Path::new("."));
+        let saved = Config::load(&saved)?;
+        config::with_layer(lua, Layer::Saved, || config::with_base_dir(lua, dir, || Config::apply(lua, &saved)))?;
+    }
+
+    log::info!("[\x1b[31mRUST\x1b[39m] Loading {init}.lua");
+    config::with_layer(lua, Layer::User, || lua.load(format!("require {init:?}")).exec())?;
+
+    let project = std::env::current_dir().ok().and_then(|dir| discovery::project_file(&dir));
+    if let Some(project) = project {
+        log::info!("[\x1b[31mRUST\x1b[39m] Loading {}", project.display());
+        config::with_layer(lua, Layer::Project, || lua.load(project.as_path()).exec())?;
+    }
+
+    config::apply_profile(lua)?;
+    config::apply_overrides(lua)?;
+
+    let config = lua.globals().get::<_, Config>("config")?;
+    let features = config.features.lock().unwrap().clone();
+    config::validate(lua, &features)
+}
+
+/// Load every config layer with [`load_config`] the first time `init` is required, e.g. by a script run
+/// with `slua run`, so the script sees the same config as every other command.
+///
+/// The selected profile is not visible through `config.active_profile` until then, as it is not applied yet.
+pub fn load_config_on_require(lua: &Lua, init: &str) -> Result<(), mlua::Error> {
+    let preload = lua.globals().get::<_, Table>("package")?.get::<_, Table>("preload")?;
+    // The built-in init when there is no init.lua, restored so `load_config` can require it
+    let builtin = lua.create_registry_value(preload.get::<_, Value>(init)?)?;
+    let profile = config::active_profile(lua);
+    config::set_profile(lua, None);
+
+    let name = init.to_string();
+    let loader = lua.create_function(move |lua, ()| {
+        let package = lua.globals().get::<_, Table>("package")?;
+        package.get::<_, Table>("preload")?.set(name.as_str(), lua.registry_value::<Value>(&builtin)?)?;
+        config::set_profile(lua, profile.clone());
+        load_config(lua, &name)?;
+        package.get::<_, Table>("loaded")?.get::<_, Value>(name.as_str())
+    })?;
+    preload.set(init, loader)
+}
+
+/// Whether the module `name` was required
+pub fn is_loaded(lua: &Lua, name: &str) -> Result<bool, mlua::Error> {
+    let loaded = lua.globals().get::<_, Table>("package")?.get::<_, Table>("loaded")?;
+    Ok(!loaded.get::<_, Value>(name)?.is_nil())
+}
+
+/// Lua language server definitions for everything [`load_std`] registers
+pub fn type_registry(lua: &Lua) -> TypeRegistry {
+    TypeRegistry::new()
+        .file(Plugins::definitions())
+        .file(MetaFile::new("v").class(
+            Class::module("v", "Root namespace")
+                .function(FunctionDef::new("print", "Pretty print all arguments to stdout").param("...", "any", "")),
+        ))
+        .file(Config::definitions(lua))
+}
+
+/// Run a lua script file with the given arguments.
+///
+/// Mirrors the standalone `lua` interpreter: the script name is stored at `arg[0]`,
+/// the arguments at `arg[1..n]` and the arguments are also passed to the chunk as `...`.
+pub fn run_script<S: AsRef<str>>(lua: &Lua, script: &std::path::Path, args: &[S]) -> Result<(), mlua::Error> {
+    let arg = lua.create_table()?;
+    arg.set(0, script.display().to_string())?;
+    for (i, value) in args.iter().enumerate() {
+        arg.set(i + 1, value.as_ref())?;
+    }
+    lua.globals().set("arg", arg)?;
+
+    log::info!("[\x1b[31mRUST\x1b[39m] Running {}", script.display());
+    lua.load(script)
+        .call(args.iter().map(AsRef::as_ref).collect::<Variadic<&str>>())
+}
+
+/// Run `f` with lua's `print` and `v.print` writing to stderr instead of stdout.
+///
+/// Used by commands whose stdout must only contain their own output, e.g. json
+/// that is piped into another program. The original functions are restored afterwards.
+pub fn with_stderr_print<R>(lua: &Lua, f: impl FnOnce() -> Result<R, mlua::Error>) -> Result<R, mlua::Error> {
+    let globals = lua.globals();
+    let v = globals.get::<_, Table>("v")?;
+    let print = globals.get::<_, Value>("print")?;
+    let pprint = v.get::<_, Value>("print")?;
+
+    globals.set("print", lua.create_function(|lua, args: Variadic<Value>| {
+        let tostring = lua.globals().get::<_, Function>("tostring")?;
+        let args = args
+            .into_iter()
+            .map(|v| tostring.call::<_, String>(v))
+            .collect::<Result<Vec<_>, _>>()?;
+        eprintln!("{}", args.join("\t"));
+        Ok(())
+    })?)?;
+    v.set("print", lua.create_function(Prettify::epprint)?)?;
+
+    let result = f();
+
+    globals.set("print", print)?;
+    v.set("print", pprint)?;
+    result
+}
@@ -0,0 +1,245 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// A field of a lua class or a parameter of a function
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub ty: String,
+    pub doc: String,
+}
+
+impl Field {
+    pub fn new<N: Into<String>, T: Into<String>, D: Into<String>>(name: N, ty: T, doc: D) -> Self {
+        Self { name: name.into(), ty: ty.into(), doc: doc.into() }
+    }
+}
+
+/// A function that is part of a module table
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub doc: String,
+    pub params: Vec<Field>,
+    pub returns: Vec<Field>,
+}
+
+impl Function {
+    pub fn new<N: Into<String>, D: Into<String>>(name: N, doc: D) -> Self {
+        Self { name: name.into(), doc: doc.into(), params: Vec::new(), returns: Vec::new() }
+    }
+
+    pub fn param<N: Into<String>, T: Into<String>, D: Into<String>>(mut self, name: N, ty: T, doc: D) -> Self {
+        self.params.push(Field::new(name, ty, doc));
+        self
+    }
+
+    pub fn returns<T: Into<String>, D: Into<String>>(mut self, ty: T, doc: D) -> Self {
+        self.returns.push(Field::new("", ty, doc));
+        self
+    }
+}
+
+/// A `---@class` definition.
+///
+/// Modules are classes that are also a global table of the same name, their
+/// functions are written as `function name.func() end` stubs.
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub name: String,
+    pub doc: String,
+    pub module: bool,
+    pub fields: Vec<Field>,
+    pub functions: Vec<Function>,
+}
+
+impl Class {
+    pub fn new<N: Into<String>, D: Into<String>>(name: N, doc: D) -> Self {
+        Self { name: name.into(), doc: doc.into(), module: false, fields: Vec::new(), functions: Vec::new() }
+    }
+
+    /// A class that is also a global table of the same name
+    pub fn module<N: Into<String>, D: Into<String>>(name: N, doc: D) -> Self {
+        Self { module: true, ..Self::new(name, doc) }
+    }
+
+    pub fn field<N: Into<String>, T: Into<String>, D: Into<String>>(mut self, name: N, ty: T, doc: D) -> Self {
+        self.fields.push(Field::new(name, ty, doc));
+        self
+    }
+
+    pub fn function(mut self, function: Function) -> Self {
+        self.functions.push(function);
+        self
+    }
+}
+
+/// Rust types that are exposed to lua and can describe themselves as a lua class
+pub trait LuaClass {
+    fn lua_class() -> Class;
+}
+
+/// A single `---@meta` file
+#[derive(Debug, Clone)]
+pub struct MetaFile {
+    pub name: String,
+    pub classes: Vec<Class>,
+    /// Global values that are an instance of a class, `(name, type)`
+    pub globals: Vec<(String, String)>,
+}
+
+impl MetaFile {
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        Self { name: name.into(), classes: Vec::new(), globals: Vec::new() }
+    }
+
+    pub fn class(mut self, class: Class) -> Self {
+        self.classes.push(class);
+        self
+    }
+
+    pub fn global<N: Into<String>, T: Into<String>>(mut self, name: N, ty: T) -> Self {
+        self.globals.push((name.into(), ty.into()));
+        self
+    }
+
+    /// Render the file contents
+    pub fn render(&self) -> String {
+        let mut out = String::from("---@meta\n-- This file is generated by `slua types generate`. Do not edit it by hand.\n");
+
+        for class in self.classes.iter() {
+            out.push('\n');
+            write_doc(&mut out, &class.doc);
+            let _ = writeln!(out, "---@class {}", class.name);
+            for field in class.fields.iter() {
+                let _ = writeln!(out, "---@field {} {}{}", field.name, field.ty, suffix(&field.doc));
+            }
+            if class.module {
+                let _ = writeln!(out, "{} = {{}}", class.name);
+            }
+
+            for function in class.functions.iter() {
+                out.push('\n');
+                write_doc(&mut out, &function.doc);
+                for param in function.params.iter() {
+                    let _ = writeln!(out, "---@param {} {}{}", param.name, param.ty, suffix(&param.doc));
+                }
+                for ret in function.returns.iter() {
+                    let _ = writeln!(out, "---@return {}{}", ret.ty, suffix(&ret.doc));
+                }
+                let _ = writeln!(
+                    out,
+                    "function {}.{}({}) end",
+                    class.name,
+                    function.name,
+                    function.params.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", "),
+                );
+            }
+        }
+
+        for (name, ty) in self.globals.iter() {
+            let _ = write!(out, "\n---@type {ty}\n{name} = nil\n");
+        }
+
+        out
+    }
+}
+
+/// All definition files that describe the lua environment
+#[derive(Debug, Clone, Default)]
+pub struct TypeRegistry {
+    pub files: Vec<MetaFile>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(mut self, file: MetaFile) -> Self {
+        self.files.push(file);
+        self
+    }
+
+    /// Write every definition file into `dir` as `<name>.lua`.
+    ///
+    /// Returns the paths of the written files.
+    pub fn generate<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut written = Vec::new();
+        for file in self.files.iter() {
+            let path = dir.join(format!("{}.lua", file.name));
+            std::fs::write(&path, file.render())?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// Create or update a `.luarc.json` so the lua language server uses lua 5.4
+/// and loads the definitions from `library`.
+///
+/// Existing settings are kept. `library` is written relative to the
+/// `.luarc.json` when it is inside of the same directory.
+pub fn write_luarc<P: AsRef<Path>, L: AsRef<Path>>(luarc: P, library: L) -> std::io::Result<()> {
+    let luarc = luarc.as_ref();
+    let mut settings = match std::fs::read_to_string(luarc) {
+        Ok(contents) => serde_json::from_str::<serde_json::Value>(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => serde_json::json!({}),
+        Err(e) => return Err(e),
+    };
+    let Some(object) = settings.as_object_mut() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} must contain a json object", luarc.display()),
+        ));
+    };
+
+    let library = library.as_ref();
+    let library = luarc
+        .parent()
+        .and_then(|parent| library.strip_prefix(parent).ok())
+        .unwrap_or(library)
+        .display()
+        .to_string();
+
+    object.insert("runtime.version".into(), "Lua 5.4".into());
+    let libraries = object
+        .entry("workspace.library")
+        .or_insert_with(|| serde_json::json!([]));
+    match libraries.as_array_mut() {
+        Some(libraries) => {
+            if !libraries.iter().any(|l| l.as_str() == Some(library.as_str())) {
+                libraries.push(library.into());
+            }
+        }
+        None => *libraries = serde_json::json!([library]),
+    }
+
+    if let Some(parent) = luarc.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(luarc, serde_json::to_string_pretty(&settings)?)
+}
+
+fn write_doc(out: &mut String, doc: &str) {
+    for line in doc.lines() {
+        match line.is_empty() {
+            true => out.push_str("---\n"),
+            false => {
+                let _ = writeln!(out, "--- {line}");
+            }
+        }
+    }
+}
+
+fn suffix(doc: &str) -> String {
+    if doc.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", doc.replace('\n', " "))
+    }
+}
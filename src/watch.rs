@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use mlua::{Error as LuaError, Function, Lua, Table, Value};
+use notify::{Event, EventKind, RecursiveMode, Watcher as _};
+
+use crate::modules::config::{self, Config};
+use crate::modules::Plugins;
+use crate::runtime;
+use crate::LuaExt;
+
+/// Time to wait for more file events before reloading, editors
+/// usually write a file with multiple events.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Re-runs the init module whenever a lua file in the config directory changes
+pub struct Watcher<'lua> {
+    lua: &'lua Lua,
+    dir: PathBuf,
+    init: String,
+}
+
+impl<'lua> Watcher<'lua> {
+    pub fn new<P: Into<PathBuf>, S: Into<String>>(lua: &'lua Lua, dir: P, init: S) -> Self {
+        Self { lua, dir: dir.into(), init: init.into() }
+    }
+
+    /// Unload every module that was loaded from the config directory, reset the
+    /// plugins and config, and load every config layer again.
+    pub fn reload(&self) -> Result<(), LuaError> {
+        let package = self.lua.globals().get::<_, Table>("package")?;
+        let loaded = package.get::<_, Table>("loaded")?;
+        let searchpath = package.get::<_, Function>("searchpath")?;
+        let path = self.lua.path();
+
+        let mut unload = Vec::new();
+        for pair in loaded.clone().pairs::<String, Value>() {
+            let (name, _) = pair?;
+            let found: Option<String> = searchpath.call((name.as_str(), path.as_str()))?;
+            if found.is_some_and(|file| Path::new(&file).starts_with(&self.dir)) {
+                unload.push(name);
+            }
+        }
+        for name in unload {
+            log::debug!("[\x1b[31mRUST\x1b[39m] Unloading {name}");
+            loaded.set(name, Value::Nil)?;
+        }
+
+        Plugins::reset(self.lua)?;
+        self.lua.globals().set("config", Config::default())?;
+        config::clear_origins(self.lua);
+        config::clear_observers(self.lua);
+        config::clear_profiles(self.lua);
+        config::clear_flags(self.lua);
+
+        // The new config is written by init.lua, frozen again afterwards if it was before
+        let frozen = config::is_frozen(self.lua);
+        config::set_frozen(self.lua, false);
+        let result = runtime::load_config(self.lua, &self.init);
+        config::set_frozen(self.lua, frozen);
+        result
+    }
+
+    /// Watch the config directory and reload on every change to a lua file.
+    ///
+    /// Blocks forever. The result of every reload is passed to `on_reload`;
+    /// lua errors do not stop watching.
+    pub fn run<F: FnMut(Result<(), LuaError>)>(&self, mut on_reload: F) -> Result<(), LuaError> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(LuaError::external)?;
+        watcher.watch(&self.dir, RecursiveMode::Recursive).map_err(LuaError::external)?;
+
+        log::info!("[\x1b[31mRUST\x1b[39m] Watching {}", self.dir.display());
+        while let Ok(event) = rx.recv() {
+            let event: Event = event.map_err(LuaError::external)?;
+            if !is_lua_change(&event) {
+                continue;
+            }
+
+            // Collapse the burst of events from a single save into one reload
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+            on_reload(self.reload());
+        }
+        Ok(())
+    }
+}
+
+fn is_lua_change(event: &Event) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+        && event.paths.iter().any(|path| path.extension().is_some_and(|ext| ext == "lua"))
+}